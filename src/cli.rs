@@ -1,38 +1,54 @@
 use std::collections::HashSet;
 use std::env;
-use std::ffi::CString;
+use std::ffi::{CString, OsStr};
 use std::hash::Hash;
-
-#[cfg(feature = "file")]
-use crate::datasource::File;
+use std::path::PathBuf;
+
+#[cfg(feature = "exec")]
+use crate::datasource::Exec;
+#[cfg(feature = "sops")]
+use crate::datasource::Sops;
+#[cfg(feature = "url")]
+use crate::datasource::Url;
 #[cfg(feature = "k8s")]
 use crate::datasource::k8s::{ConfigMap, Secret};
-use crate::datasource::{Environment, Source, SourceRegistry};
+use crate::datasource::{
+    DocumentFormat, Environment, Layer, MergeStrategy, Source, SourceRegistry, Stdin,
+};
+#[cfg(feature = "file")]
+use crate::datasource::{EnvFile, File};
+use crate::dump::DumpFormat;
 use crate::error::{Error, Result};
 use crate::plan::{Plan, TemplateDestination, TemplateOperation, TemplateSource};
-use crate::reload::{OnReloadAction, OnReloadSignalTarget};
-#[cfg(feature = "poll")]
+use crate::reload::{HookPolicy, OnReloadAction, OnReloadSignalTarget, parse_signal};
+#[cfg(feature = "schema")]
+use crate::schema::Schema;
 use clap::builder::TypedValueParser;
-#[cfg(feature = "poll")]
 use clap::builder::ValueParser;
 use clap::error::ErrorKind;
 use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command, ValueHint, value_parser};
 use clap_complete::{Generator, Shell, generate};
 use indoc::indoc;
-use nix::sys::signal::Signal;
 use shadow_rs::shadow;
 
 shadow!(build);
 
 pub struct Cli {
     matches: ArgMatches,
+
+    /// The sources built while validating, handed out by [Cli::sources].
+    sources: Option<SourceRegistry>,
 }
 
 impl Cli {
     pub fn new() -> Result<Self> {
         let mut app = command();
         let matches = app.try_get_matches_from_mut(env::args_os())?;
-        Self { matches }.validate(&mut app)
+        Self {
+            matches,
+            sources: None,
+        }
+        .validate(&mut app)
     }
 
     #[cfg(test)]
@@ -43,10 +59,14 @@ impl Cli {
     {
         let mut app = command();
         let matches: ArgMatches = app.try_get_matches_from_mut(itr)?;
-        Self { matches }.validate(&mut app)
+        Self {
+            matches,
+            sources: None,
+        }
+        .validate(&mut app)
     }
 
-    fn validate(self, cmd: &mut Command) -> Result<Self> {
+    fn validate(mut self, cmd: &mut Command) -> Result<Self> {
         // no two operations write to the same output
         let plan = self.plan();
         let destinations: Vec<_> = plan.iter().map(|op| &op.dest).collect();
@@ -70,35 +90,181 @@ impl Cli {
             Err(Error::ClapError(e))?
         }
 
+        let sources = match self.build_sources() {
+            Ok(sources) => sources,
+            Err(e) => {
+                let e = cmd.error(ErrorKind::ValueValidation, e);
+                Err(Error::ClapError(e))?
+            }
+        };
+
+        // standard input can only be consumed once
+        let stdin_sources = sources
+            .sources
+            .iter()
+            .filter(|layer| layer.source.reads_stdin())
+            .count();
+        let stdin_templates = plan
+            .iter()
+            .filter(|op| op.source == TemplateSource::StdIn)
+            .count();
+        if stdin_sources > 1
+            || (stdin_sources == 1 && stdin_templates > 0 && self.dump_context().is_none())
+        {
+            let e = cmd.error(
+                ErrorKind::ArgumentConflict,
+                "Standard input can only be read by a single data source or template",
+            );
+            Err(Error::ClapError(e))?
+        }
+
+        if let Err(e) = self.function_options() {
+            let e = cmd.error(ErrorKind::ValueValidation, e);
+            Err(Error::ClapError(e))?
+        }
+
+        self.sources = Some(sources);
         Ok(self)
     }
 
-    fn get_source_from_spec<S1, S2>(
-        &self,
-        source_type: S1,
-        arg: Option<S2>,
-    ) -> Box<dyn Source + Send + Sync>
+    fn get_source_from_spec<S1, S2>(&self, source_type: S1, arg: Option<S2>) -> Result<Layer>
     where
         S1: AsRef<str>,
         S2: AsRef<str>,
     {
-        match source_type.as_ref() {
-            "environment" => Box::new(Environment::new(match arg {
-                None => None,
-                Some(prefix) if prefix.as_ref().is_empty() => None,
-                prefix => prefix,
-            })),
+        let (arg, mut options) = match arg {
+            Some(ref arg) => {
+                let (arg, options) = SourceOptions::split(arg.as_ref());
+                (Some(arg), options)
+            }
+            None => (None, SourceOptions::default()),
+        };
+
+        let mount = options.take("mount");
+        if let Some(mount) = mount
+            && !is_mount(mount)
+        {
+            Err(Error::CliInvalidSourceOption(format!(
+                "invalid mount point '{mount}'"
+            )))?
+        }
+
+        let strategy = options
+            .take("merge")
+            .map(str::parse::<MergeStrategy>)
+            .transpose()?
+            .unwrap_or_default();
+
+        let source: Box<dyn Source + Send + Sync> = match source_type.as_ref() {
+            "environment" => Box::new(environment_from_options(
+                match arg {
+                    None => None,
+                    Some("") => None,
+                    prefix => prefix,
+                },
+                &mut options,
+            )?),
             #[cfg(feature = "file")]
-            "file" => Box::new(File::new(arg.unwrap().as_ref())),
+            "file" => {
+                let format = options
+                    .take("format")
+                    .map(str::parse::<DocumentFormat>)
+                    .transpose()?;
+                match arg.unwrap() {
+                    "-" => Box::new(Stdin::new(format.unwrap_or(DocumentFormat::Json))),
+                    path => Box::new(File::new(path).with_format(format)),
+                }
+            }
+            #[cfg(feature = "file")]
+            "envfile" => {
+                let prefix = options.take("prefix");
+                let env = environment_from_options(prefix, &mut options)?;
+                Box::new(EnvFile::new(arg.unwrap(), env))
+            }
+            "stdin" => Box::new(Stdin::new(match arg {
+                None | Some("") => DocumentFormat::Json,
+                Some(format) => format.parse()?,
+            })),
+            #[cfg(feature = "sops")]
+            "sops" => Box::new(Sops::new(arg.unwrap()).with_key_file(options.take("key-file"))),
+            #[cfg(feature = "url")]
+            "url" => {
+                let url: reqwest::Url = arg
+                    .unwrap()
+                    .parse()
+                    .map_err(|e| Error::CliInvalidSourceOption(format!("invalid URL: {e}")))?;
+                let interval = options
+                    .take("interval")
+                    .map(parse_interval)
+                    .transpose()?
+                    .unwrap_or(Url::DEFAULT_INTERVAL);
+
+                // the default profile applies if none is selected, as for http().
+                #[cfg(feature = "http")]
+                let source = {
+                    use crate::functions::{ClientProfile, RequestPolicy};
+
+                    let name = options.take("profile");
+                    let selected = name.unwrap_or(ClientProfile::DEFAULT);
+                    match (self.http_profiles()?.remove(selected), name) {
+                        (Some(profile), _) => {
+                            let host = url.host_str().unwrap_or_default();
+                            let client =
+                                profile
+                                    .build(&RequestPolicy::default(), host)
+                                    .map_err(|e| {
+                                        Error::CliInvalidHttpProfile(format!("{selected}: {e}"))
+                                    })?;
+                            let (target, host) = profile.route(&url);
+                            Url::new(target, interval)
+                                .with_client(client)
+                                .with_host(host)
+                        }
+                        (None, Some(name)) => Err(Error::CliInvalidSourceOption(format!(
+                            "unknown HTTP profile '{name}'"
+                        )))?,
+                        (None, None) => Url::new(url, interval),
+                    }
+                };
+                #[cfg(not(feature = "http"))]
+                let source = Url::new(url, interval);
+
+                Box::new(source)
+            }
+            #[cfg(feature = "exec")]
+            "exec" => {
+                let format = options
+                    .take("format")
+                    .map(str::parse::<DocumentFormat>)
+                    .transpose()?
+                    .unwrap_or(DocumentFormat::Json);
+                let fatal = match options.take("on-error") {
+                    None | Some("skip") => false,
+                    Some("fail") => true,
+                    Some(other) => Err(Error::CliInvalidSourceOption(format!(
+                        "on-error must be skip or fail, got '{other}'"
+                    )))?,
+                };
+                let interval = options.take("interval").map(parse_interval).transpose()?;
+                Box::new(
+                    Exec::new(arg.unwrap(), format)
+                        .with_fatal(fatal)
+                        .with_interval(interval),
+                )
+            }
             #[cfg(feature = "k8s")]
             "k8s-configmap" => Box::new(ConfigMap::new(arg.unwrap(), self.k8s_namespace())),
             #[cfg(feature = "k8s")]
             "k8s-secret" => Box::new(Secret::new(arg.unwrap(), self.k8s_namespace())),
             _ => unreachable!(),
-        }
+        };
+
+        options.finish()?;
+
+        Ok(Layer::new(source).with_mount(mount).with_strategy(strategy))
     }
 
-    fn parse_source_env_variable(&self, value: &str) -> Vec<Box<dyn Source + Send + Sync>> {
+    fn parse_source_env_variable(&self, value: &str) -> Result<Vec<Layer>> {
         value
             .split(',')
             .map(|source_spec| {
@@ -114,10 +280,19 @@ impl Cli {
     ///
     /// Sources are taken from command line arguments and the `CONTEMPLATE_DATASOURCES` environment variable.
     /// Sources specified later override earlier ones, and command line arguments override environment variables.
-    pub fn sources(&self) -> SourceRegistry {
+    /// The sources built while validating the arguments are returned by the first call.
+    pub fn sources(&mut self) -> Result<SourceRegistry> {
+        match self.sources.take() {
+            Some(sources) => Ok(sources),
+            None => self.build_sources(),
+        }
+    }
+
+    fn build_sources(&self) -> Result<SourceRegistry> {
         let sources_from_env = env::var("CONTEMPLATE_DATASOURCES")
             .ok()
             .map(|value| self.parse_source_env_variable(&value))
+            .transpose()?
             .into_iter()
             .flatten();
 
@@ -125,6 +300,14 @@ impl Cli {
             "environment",
             #[cfg(feature = "file")]
             "file",
+            #[cfg(feature = "file")]
+            "envfile",
+            #[cfg(feature = "sops")]
+            "sops",
+            #[cfg(feature = "url")]
+            "url",
+            #[cfg(feature = "exec")]
+            "exec",
             #[cfg(feature = "k8s")]
             "k8s-configmap",
             #[cfg(feature = "k8s")]
@@ -145,14 +328,23 @@ impl Cli {
 
         sources.sort_by_key(|(_, _, a)| *a);
 
-        let sources_from_args =
-            sources
-                .into_iter()
-                .map(|(source_type, arg, _)| -> Box<dyn Source + Sync + Send> {
-                    self.get_source_from_spec(source_type, arg)
-                });
+        let sources_from_args = sources
+            .into_iter()
+            .map(|(source_type, arg, _)| self.get_source_from_spec(source_type, arg))
+            .collect::<Result<Vec<_>>>()?;
+
+        let registry = SourceRegistry::new(sources_from_env.chain(sources_from_args));
+
+        #[cfg(feature = "schema")]
+        let registry = registry.with_schema(self.schema().map(Schema::from_path).transpose()?);
+
+        Ok(registry)
+    }
 
-        SourceRegistry::new(sources_from_env.chain(sources_from_args))
+    /// The schema argument
+    #[cfg(feature = "schema")]
+    pub fn schema(&self) -> Option<&String> {
+        self.matches.get_one::<String>("schema")
     }
 
     pub fn template_args(&self) -> Vec<TemplateOperation> {
@@ -223,61 +415,102 @@ impl Cli {
             .collect()
     }
 
-    pub fn signal_arg(&self) -> Result<Option<(Signal, OnReloadSignalTarget)>> {
-        let Some(args) = self.matches.get_raw("on-reload-signal") else {
-            return Ok(None);
+    /// The raw values of every occurrence of an argument, along with its position on the command line.
+    fn occurrences(&self, id: &str) -> Vec<(usize, Vec<&OsStr>)> {
+        let (Some(occurrences), Some(mut indices)) = (
+            self.matches.get_raw_occurrences(id),
+            self.matches.indices_of(id),
+        ) else {
+            return vec![];
         };
 
-        let args = args.into_iter().collect::<Vec<_>>();
+        occurrences
+            .map(|values| {
+                let values = values.collect::<Vec<_>>();
+                // an occurrence has an index for each of its values.
+                let index = indices.next().unwrap_or_default();
+                for _ in 1..values.len() {
+                    indices.next();
+                }
+                (index, values)
+            })
+            .collect()
+    }
 
-        if let Some(signal) = args[0]
-            .to_str()
-            .and_then(|s| s.parse().ok())
-            .and_then(|signum: i32| Signal::try_from(signum).ok())
-        {
-            let target = args.get(1).map(|s| (*s).into()).unwrap_or_default();
-            return Ok(Some((signal, target)));
-        }
+    /// Return the user-specified on-reload actions, in the order they were given.
+    pub fn on_reload(&self) -> Result<Vec<OnReloadAction>> {
+        let mut actions = vec![];
 
-        if let Some(signal) = args[0].to_str().and_then(|s| s.to_uppercase().parse().ok()) {
-            let target = args.get(1).map(|s| (*s).into()).unwrap_or_default();
-            return Ok(Some((signal, target)));
+        for (index, command) in self.occurrences("on-reload-command") {
+            actions.push((index, OnReloadAction::ShellCommand(command[0].to_owned())));
         }
 
-        if let Some(signal) = args[0]
-            .to_str()
-            .and_then(|s| format!("SIG{}", s.to_uppercase()).parse().ok())
-        {
-            let target = args.get(1).map(|s| (*s).into()).unwrap_or_default();
-            return Ok(Some((signal, target)));
+        for (index, argv) in self.occurrences("on-reload-exec") {
+            let path = argv[0].to_owned();
+            let args = argv[1..].iter().map(|arg| (*arg).to_owned()).collect();
+            actions.push((index, OnReloadAction::Executable { path, args }));
         }
 
-        Err(Error::CliInvalidSignal)
-    }
+        for (index, args) in self.occurrences("on-reload-signal") {
+            let signal = args[0]
+                .to_str()
+                .and_then(parse_signal)
+                .ok_or(Error::CliInvalidSignal)?;
+            let target = args
+                .get(1)
+                .map(|s| OnReloadSignalTarget::try_from(*s).map_err(Error::CliInvalidSignalTarget))
+                .transpose()?
+                .unwrap_or_default();
+            actions.push((index, OnReloadAction::Signal { signal, target }));
+        }
 
-    /// Return the user-specified on-reload action, if available.
-    pub fn on_reload(&self) -> Result<OnReloadAction> {
-        if let Some(command) = self
-            .matches
-            .get_raw("on-reload-command")
-            .and_then(|mut opt| opt.next())
-        {
-            return Ok(OnReloadAction::ShellCommand(command.to_owned()));
+        if self.matches.get_flag("on-reload-restart") {
+            let signal = self.matches.get_one::<String>("restart-signal").unwrap();
+            let action = OnReloadAction::Restart {
+                signal: parse_signal(signal).ok_or(Error::CliInvalidSignal)?,
+                grace_period: *self.matches.get_one("restart-grace-period").unwrap(),
+                min_interval: *self.matches.get_one("restart-interval").unwrap(),
+            };
+            let index = self
+                .matches
+                .index_of("on-reload-restart")
+                .unwrap_or_default();
+            actions.push((index, action));
         }
 
-        if let Some(executable) = self
-            .matches
-            .get_raw("on-reload-exec")
-            .and_then(|mut opt| opt.next())
-        {
-            return Ok(OnReloadAction::Executable(executable.to_owned()));
+        #[cfg(feature = "reload-http")]
+        for (index, url) in self.occurrences("on-reload-http") {
+            let invalid = Error::CliInvalidReloadHttp;
+            let spec = url[0].to_string_lossy();
+            let (url, mut options) = SourceOptions::split(&spec);
+            let headers = options
+                .take_all("header")
+                .into_iter()
+                .map(|header| parse_http_header(header).map_err(invalid))
+                .collect::<Result<_>>()?;
+            let token_file = options.take("token-file").map(PathBuf::from);
+            options.finish().map_err(|e| match e {
+                Error::CliInvalidSourceOption(msg) => invalid(msg),
+                e => e,
+            })?;
+            let target = crate::reload::HttpTarget {
+                url: url
+                    .parse()
+                    .map_err(|e| invalid(format!("invalid URL '{url}': {e}")))?,
+                headers,
+                token_file,
+            };
+            actions.push((index, OnReloadAction::Http(target)));
         }
 
-        if let Some((signal, target)) = self.signal_arg()? {
-            return Ok(OnReloadAction::Signal { signal, target });
+        #[cfg(feature = "dbus")]
+        for (index, unit) in self.occurrences("on-reload-unit") {
+            let unit = crate::systemd::unit_name(&unit[0].to_string_lossy());
+            actions.push((index, OnReloadAction::SystemdReload(unit)));
         }
 
-        Ok(OnReloadAction::None)
+        actions.sort_by_key(|(index, _)| *index);
+        Ok(actions.into_iter().map(|(_, action)| action).collect())
     }
 
     /// Get the value of the `--and-then-exec` / `-x` argument.
@@ -386,6 +619,29 @@ impl Cli {
         }
     }
 
+    /// How reload hooks are timed out and retried.
+    pub fn hook_policy(&self) -> HookPolicy {
+        HookPolicy {
+            timeout: self.matches.get_one("on-reload-timeout").copied(),
+            retries: *self.matches.get_one("on-reload-retries").unwrap(),
+            backoff: *self.matches.get_one("on-reload-backoff").unwrap(),
+            keep_going: self
+                .matches
+                .get_one::<String>("on-reload-on-failure")
+                .is_some_and(|policy| policy == "continue"),
+        }
+    }
+
+    /// The file to write the status of renders and reload hooks to
+    pub fn status_file(&self) -> Option<&PathBuf> {
+        self.matches.get_one("status-file")
+    }
+
+    /// Was supervise arg given
+    pub fn supervise(&self) -> bool {
+        self.matches.get_flag("supervise")
+    }
+
     /// Was daemonize arg given
     pub fn daemonize(&self) -> bool {
         if let Some(daemonize) = self.matches.get_one("daemonize") {
@@ -395,6 +651,88 @@ impl Cli {
         }
     }
 
+    /// The format to dump the context in, if the dump-context arg was given
+    pub fn dump_context(&self) -> Option<DumpFormat> {
+        match self.matches.get_one::<String>("dump-context")?.as_str() {
+            "json" => Some(DumpFormat::Json),
+            #[cfg(feature = "yaml")]
+            "yaml" => Some(DumpFormat::Yaml),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Was explain arg given
+    pub fn explain(&self) -> bool {
+        if let Some(explain) = self.matches.get_one("explain") {
+            *explain
+        } else {
+            false
+        }
+    }
+
+    /// The client profiles defined using --http-profile, by name.
+    #[cfg(feature = "http")]
+    fn http_profiles(
+        &self,
+    ) -> Result<std::collections::HashMap<String, crate::functions::ClientProfile>> {
+        let mut profiles = std::collections::HashMap::new();
+        for spec in self
+            .matches
+            .get_many::<String>("http-profile")
+            .into_iter()
+            .flatten()
+        {
+            let (name, profile) = parse_http_profile(spec)?;
+            // fail at startup, rather than on the first request using the profile.
+            profile
+                .load()
+                .map_err(|e| Error::CliInvalidHttpProfile(format!("{name}: {e}")))?;
+            if profiles.contains_key(&name) {
+                return Err(Error::CliInvalidHttpProfile(format!(
+                    "{name}: profile defined more than once"
+                )));
+            }
+            profiles.insert(name, profile);
+        }
+
+        Ok(profiles)
+    }
+
+    /// Options for the functions available in templates
+    pub fn function_options(&self) -> Result<crate::functions::Options> {
+        #[allow(unused_mut)]
+        let mut options = crate::functions::Options::default();
+
+        #[cfg(feature = "http")]
+        {
+            let http = &mut options.http;
+            if let Some(timeout) = self.matches.get_one("http-connect-timeout") {
+                http.connect_timeout = *timeout;
+            }
+            if let Some(timeout) = self.matches.get_one("http-read-timeout") {
+                http.read_timeout = *timeout;
+            }
+            if let Some(retries) = self.matches.get_one("http-retries") {
+                http.retries = *retries;
+            }
+            if let Some(backoff) = self.matches.get_one("http-backoff") {
+                http.backoff = *backoff;
+            }
+            http.fallback = self.matches.get_flag("http-fallback");
+
+            options.http_cache = self
+                .matches
+                .get_one::<String>("http-cache-dir")
+                .map(ToOwned::to_owned)
+                .or_else(|| env::var("CONTEMPLATE_HTTP_CACHE_DIR").ok())
+                .map(crate::functions::DiskCache::new);
+
+            options.http_profiles = self.http_profiles()?;
+        }
+
+        Ok(options)
+    }
+
     pub fn plan(&self) -> Plan {
         let mut ops = self.intput_output_args();
         ops.extend(self.template_args());
@@ -489,6 +827,32 @@ fn command() -> Command {
                 .help("Don't write to any files")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("dump-context")
+                .long("dump-context")
+                .help("Print the merged context and exit")
+                .long_help(indoc! {
+                    "Print the context merged from all data sources and exit without rendering.
+
+                    FORMAT defaults to json."
+                })
+                .value_name("FORMAT")
+                .value_parser([
+                    "json",
+                    #[cfg(feature = "yaml")]
+                    "yaml",
+                ])
+                .num_args(0..=1)
+                .default_missing_value("json")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .help("Annotate each value in the dumped context with its data source")
+                .action(ArgAction::SetTrue)
+                .requires("dump-context"),
+        )
         .arg(
             Arg::new("environment")
                 .short('e')
@@ -500,6 +864,14 @@ fn command() -> Command {
                     
                     If PREFIX is specified, only environment variables starting with PREFIX will be
                     passed to the template. The PREFIX will be stripped from the variable names.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
+
+                    Names are nested at '_', or the separator given using ';separator=SEP'
+                    (empty to keep names flat), and lowercased unless ';case=preserve' is given.
+                    Values are parsed into numbers, booleans, lists and dictionaries, unless
+                    ';parse=false' is given. Variables can be selected using ';allow=NAME' and
+                    ';deny=NAME', which can be repeated, and end in '*' to match a prefix.
                     
                     Can be specified multiple times with distinct PREFIX values."
                 })
@@ -518,6 +890,10 @@ fn command() -> Command {
                     "k8s-secret",
                     #[cfg(feature = "file")]
                     "file",
+                    #[cfg(feature = "file")]
+                    "envfile",
+                    #[cfg(feature = "sops")]
+                    "sops",
                     "environment",
                 ])
                 .multiple(true),
@@ -601,7 +977,9 @@ fn command() -> Command {
                     "Execute the specified shell command on reload.
                     
                     The path to the templated config files is specified in
-                    the CONTEMPLATED_FILES environment variable.
+                    the CONTEMPLATED_FILES environment variable, and a JSON manifest
+                    of the changed files in CONTEMPLATED_MANIFEST. See the documentation
+                    for further variables.
                     If the command is still running while a new change is
                     detected, the SIGINT signal will be sent to it before re-executing,
                     enabling the downstream hook to debounce changes
@@ -609,42 +987,141 @@ fn command() -> Command {
                     
                     Example: 'killall -SIGHUP nginx'"
                 })
-                .num_args(1),
+                .num_args(1)
+                .action(ArgAction::Append),
         )
         .arg(
             Arg::new("on-reload-exec")
                 .long("on-reload-exec")
                 .short('R')
                 .value_name("EXECUTABLE")
-                .value_hint(ValueHint::ExecutablePath)
+                .value_hint(ValueHint::CommandName)
                 .help("Execute the specified executable on reload without a shell")
                 .long_help(indoc! {
                     "Execute the specified executable on reload without a shell
 
+                    Any following values are passed verbatim as arguments up to
+                    the delimiter ';', like for --and-then-exec.
                     See -r for environment variables and signals.
                     
-                    Example: '/usr/local/bin/reload-nginx'"
-                }),
+                    Example: '/usr/local/bin/reload-nginx --graceful ;'"
+                })
+                .action(ArgAction::Append)
+                .num_args(1..)
+                .value_terminator(";")
+                .allow_hyphen_values(true),
         )
         .arg(
             Arg::new("on-reload-signal")
                 .long("on-reload-signal")
-                .value_names(["SIGNAL", "PID|PROCNAME"])
+                .value_names(["SIGNAL", "TARGET"])
                 .help("On reload, send a signal to the specified PID or process name.")
                 .long_help(indoc! {
-                    "On reload, send a signal to the specified PID or process name.
+                    "On reload, send a signal to the specified target, which is one of:
+                      PID               the process with the given PID
+                      PROCNAME          processes whose name contains PROCNAME
+                      exe:NAME          processes whose executable is named exactly NAME
+                      cmdline:REGEX     processes whose command line matches REGEX
+                      pidfile:PATH      the process whose PID is in the file at PATH
+                      cgroup:PATH       the processes in the cgroup at PATH, relative to
+                                        /sys/fs/cgroup, and its descendants
+                      :parent           the process executed by -x
+                      :parent-group     the process group of the process executed by -x,
+                                        requires --supervise
+
+                    ':parent' is the default when no target is given."
+                })
+                .num_args(1..=2)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("on-reload-restart")
+                .long("on-reload-restart")
+                .action(ArgAction::SetTrue)
+                .help("On reload, restart the supervised child")
+                .long_help(indoc! {
+                    "On reload, restart the --supervise'd child, for software that can't reload
+                    its configuration.
 
-                    The special process name ':parent' can be used to specify the
-                    parent process when -x is specified to signal the executed process.
-                    This is the default when no PID or process name is given."
+                    The child is sent the --restart-signal, killed if it doesn't exit within
+                    the --restart-grace-period, and started again with the same arguments.
+                    Restarts are at least --restart-interval apart, and changes in the
+                    meantime are applied in a single restart."
+                })
+                .requires_all(["supervise", "watch"]),
+        )
+        .arg(
+            Arg::new("restart-signal")
+                .long("restart-signal")
+                .value_name("SIGNAL")
+                .help("The signal asking the child to stop when restarting")
+                .default_value("TERM")
+                .requires("on-reload-restart"),
+        )
+        .arg(
+            Arg::new("restart-grace-period")
+                .long("restart-grace-period")
+                .value_name("DURATION")
+                .help("How long to wait for the child to stop when restarting, before killing it")
+                .value_parser(ValueParser::new(HumanDurationParser {}))
+                .default_value("10s")
+                .requires("on-reload-restart"),
+        )
+        .arg(
+            Arg::new("restart-interval")
+                .long("restart-interval")
+                .value_name("DURATION")
+                .help("The minimum time between restarts")
+                .value_parser(ValueParser::new(HumanDurationParser {}))
+                .default_value("5s")
+                .requires("on-reload-restart"),
+        )
+        .arg(
+            Arg::new("on-reload-timeout")
+                .long("on-reload-timeout")
+                .value_name("DURATION")
+                .help("Kill reload hooks running longer than the given duration, and fail them")
+                .value_parser(ValueParser::new(HumanDurationParser {})),
+        )
+        .arg(
+            Arg::new("on-reload-retries")
+                .long("on-reload-retries")
+                .value_name("COUNT")
+                .help("Retry failed reload hooks the given number of times")
+                .value_parser(value_parser!(u32))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("on-reload-backoff")
+                .long("on-reload-backoff")
+                .value_name("DURATION")
+                .help("How long to wait before retrying a failed reload hook, doubling every retry")
+                .value_parser(ValueParser::new(HumanDurationParser {}))
+                .default_value("1s"),
+        )
+        .arg(
+            Arg::new("on-reload-on-failure")
+                .long("on-reload-on-failure")
+                .value_name("POLICY")
+                .help(
+                    "Whether to stop or continue with the following on-reload actions if one fails",
+                )
+                .value_parser(["stop", "continue"])
+                .default_value("stop"),
+        )
+        .arg(
+            Arg::new("status-file")
+                .long("status-file")
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .help("Write the status of renders and reload hooks to the given JSON file")
+                .long_help(indoc! {
+                    "Write the status of renders and reload hooks to the given JSON file,
+                    e.g. for health checks. The file is replaced whenever the status changes."
                 })
-                .num_args(1..=2),
+                .value_parser(value_parser!(PathBuf))
+                .requires("watch"),
         )
-        .group(ArgGroup::new("on-reload").args([
-            "on-reload-command",
-            "on-reload-exec",
-            "on-reload-signal",
-        ]))
         .arg(
             Arg::new("and-then-exec")
                 .long("and-then-exec")
@@ -667,6 +1144,23 @@ fn command() -> Command {
                 .value_terminator(";")
                 .allow_hyphen_values(true),
         )
+        .arg(
+            Arg::new("supervise")
+                .long("supervise")
+                .action(ArgAction::SetTrue)
+                .help("Run the --and-then-exec executable as a supervised child")
+                .long_help(indoc! {
+                    "Run the --and-then-exec executable as a child, rather than executing it.
+
+                    Contemplate stays in the foreground, e.g. as PID 1 of a container,
+                    forwards signals to the child, reaps orphaned processes, and exits
+                    with the exit code of the child once it exits. In watch mode, SIGHUP
+                    and SIGUSR1 are handled by Contemplate instead of being forwarded,
+                    and ':parent' of --on-reload-signal refers to the child."
+                })
+                .requires("and-then-exec")
+                .conflicts_with("daemonize"),
+        )
         .arg(
             Arg::new("print-shell-completions")
                 .long("print-shell-completions")
@@ -716,13 +1210,119 @@ fn command() -> Command {
                 .long("file")
                 .help("Add a file as a data source")
                 .long_help(indoc! {
-                    "Add a file as a data source. The file must be a valid JSON, YAML, TOML, ini,
-                    JSON5 or RON file. The file format is guessed using its file extension.
+                    "Add a file as a data source. The file must be a valid JSON, YAML, TOML, JSON5,
+                    RON, ini, Java properties or dotenv file. The file format is guessed using its
+                    file extension, and can be given explicitly using ';format=FORMAT'.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
+
+                    Use '-' to read a JSON document, or one in the given format, from standard input.
+                    Standard input can't be used for the template at the same time.
 
                     Can be specified multiple times to add multiple file data sources"
                 })
                 .value_name("PATH")
                 .value_hint(ValueHint::FilePath)
+                .allow_hyphen_values(true)
+                .action(ArgAction::Append),
+        )
+    }
+
+    #[cfg(feature = "file")]
+    {
+        command = command.arg(
+            Arg::new("envfile")
+                .long("env-file")
+                .help("Add a file of environment variables as a data source")
+                .long_help(indoc! {
+                    "Add a file of KEY=VALUE lines as a data source, like a mounted app.env.
+                    Quoting, 'export' and comments are supported as in dotenv files.
+                    Variables are normalized like with --env, using the same options, and can be
+                    filtered by a prefix using ';prefix=PREFIX'.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
+
+                    Unlike the process environment, the file is watched for changes in watch mode.
+
+                    Can be specified multiple times to add multiple env file data sources"
+                })
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .action(ArgAction::Append),
+        )
+    }
+
+    #[cfg(feature = "sops")]
+    {
+        command = command.arg(
+            Arg::new("sops")
+                .long("sops")
+                .help("Add a SOPS encrypted file as a data source")
+                .long_help(indoc! {
+                    "Add a JSON or YAML file encrypted using SOPS with age as a data source.
+                    The file is decrypted in-process, using the age key file given using
+                    ';key-file=PATH', or like sops, the key in SOPS_AGE_KEY, the key file in
+                    SOPS_AGE_KEY_FILE or ~/.config/sops/age/keys.txt.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
+                    Decrypted values are redacted when dumping the context.
+
+                    Can be specified multiple times to add multiple SOPS data sources"
+                })
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .action(ArgAction::Append),
+        )
+    }
+
+    #[cfg(feature = "url")]
+    {
+        command = command.arg(
+            Arg::new("url")
+                .short('u')
+                .long("url")
+                .help("Add a document fetched over HTTP as a data source")
+                .long_help(indoc! {
+                    "Add a document fetched over HTTP as a data source. The document must be
+                    JSON, YAML or TOML, as given by its Content-Type, or by the extension of the URL.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
+                    Append ';profile=NAME' to use a client profile defined using --http-profile.
+
+                    In watch mode, the URL is polled every 30 seconds, or the interval given using
+                    ';interval=DURATION', triggering a reload if the document changed.
+
+                    Can be specified multiple times to add multiple URL data sources"
+                })
+                .value_name("URL")
+                .value_hint(ValueHint::Url)
+                .action(ArgAction::Append),
+        )
+    }
+
+    #[cfg(feature = "exec")]
+    {
+        command = command.arg(
+            Arg::new("exec")
+                .long("exec")
+                .help("Add the output of a shell command as a data source")
+                .long_help(indoc! {
+                    "Add the output of a shell command as a data source. The command is run using
+                    /bin/sh, and its standard output parsed as JSON, or the format given using
+                    ';format=json|yaml|toml'.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
+
+                    If the command fails, the data source is skipped with a warning.
+                    Append ';on-error=fail' to fail instead.
+
+                    In watch mode, the command is re-run in the interval given using
+                    ';interval=DURATION', triggering a reload if its output changed.
+
+                    Can be specified multiple times to add multiple command data sources"
+                })
+                .value_name("COMMAND")
+                .value_hint(ValueHint::CommandString)
                 .action(ArgAction::Append),
         )
     }
@@ -751,6 +1351,8 @@ fn command() -> Command {
                         "Add a kubernetes configmap as a data source for template variables.
                     A kubernetes service account credential needs to be present in
                     /var/run/secrets/kubernetes.io/serviceaccount/token.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
 
                     Can be specified multiple times to add multiple config maps"
                     })
@@ -766,6 +1368,8 @@ fn command() -> Command {
                         "Add a kubernetes secret as a data source for template variables.
                     A kubernetes service account credential needs to be present in
                     /var/run/secrets/kubernetes.io/serviceaccount/token.
+                    The values can be mounted under a key using ';mount=MOUNT'.
+                    Append ';merge=STRATEGY' to use the join, adjoin or admerge strategy.
 
                     Can be specified multiple times to add multiple secret"
                     })
@@ -775,6 +1379,160 @@ fn command() -> Command {
             );
     }
 
+    #[cfg(feature = "schema")]
+    {
+        command = command.arg(
+            Arg::new("schema")
+                .long("schema")
+                .help("Validate the context against a JSON schema")
+                .long_help(indoc! {
+                    "Validate the context merged from all data sources against a JSON schema
+                    before rendering.
+
+                    If validation fails on the initial render, contemplate exits with an error.
+                    In watch mode, templates are not re-rendered until the context is valid again."
+                })
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .action(ArgAction::Set),
+        )
+    }
+
+    #[cfg(feature = "http")]
+    {
+        command = command
+            .arg(
+                Arg::new("http-connect-timeout")
+                    .long("http-connect-timeout")
+                    .help("Default connect timeout of the http() function [default: 10s]")
+                    .value_name("DURATION")
+                    .value_parser(ValueParser::new(HumanDurationParser {})),
+            )
+            .arg(
+                Arg::new("http-read-timeout")
+                    .long("http-read-timeout")
+                    .help("Default read timeout of the http() function [default: 30s]")
+                    .value_name("DURATION")
+                    .value_parser(ValueParser::new(HumanDurationParser {})),
+            )
+            .arg(
+                Arg::new("http-retries")
+                    .long("http-retries")
+                    .help("Default number of retries of the http() function [default: 0]")
+                    .long_help(indoc! {
+                        "Default number of times the http() function retries a request
+                        on connection errors, timeouts and 5xx responses. [default: 0]"
+                    })
+                    .value_name("COUNT")
+                    .value_parser(value_parser!(u32)),
+            )
+            .arg(
+                Arg::new("http-backoff")
+                    .long("http-backoff")
+                    .help("Default delay before the first retry of the http() function [default: 1s]")
+                    .long_help(indoc! {
+                        "Default delay before the first retry of the http() function.
+                        The delay doubles with each further retry. [default: 1s]"
+                    })
+                    .value_name("DURATION")
+                    .value_parser(ValueParser::new(HumanDurationParser {})),
+            )
+            .arg(
+                Arg::new("http-fallback")
+                    .long("http-fallback")
+                    .help("Let the http() function return the last cached response if a request fails")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("http-profile")
+                    .long("http-profile")
+                    .help("Define a client profile selectable in the http() function")
+                    .long_help(indoc! {
+                        "Define a client profile selectable in the http() function using the
+                        `profile` keyword argument. A profile named `default` is used for
+                        requests not selecting a profile.
+
+                        The profile is given as NAME, followed by any of these options:
+                          ;ca=PATH            trust the CA certificates in the given PEM bundle
+                          ;cert=PATH          present the given PEM client certificate
+                          ;key=PATH           use the given PEM private key for the client certificate
+                          ;resolve=HOST:ADDR  connect to ADDR for HOST, may be given multiple times
+                          ;sni=NAME           present and verify NAME as TLS server name to the
+                                              host of HTTPS URLs. Cannot be combined with a proxy
+                          ;proxy=URL          send all requests through the given proxy
+                          ;no-proxy=HOSTS     comma-separated hosts to bypass the proxy for
+                          ;insecure=true      skip verification of server certificates
+
+                        Example: --http-profile 'internal;ca=/etc/ssl/internal.pem;cert=client.pem;key=client.key'"
+                    })
+                    .value_name("SPEC")
+                    .action(ArgAction::Append),
+            )
+            .arg(
+                Arg::new("http-cache-dir")
+                    .long("http-cache-dir")
+                    .help("Persist responses of the http() function in the given directory")
+                    .long_help(indoc! {
+                        "Persist responses of the http() function in the given directory.
+
+                        Persisted responses are used for conditional requests across renders
+                        and restarts, and are served stale if the server is unreachable.
+
+                        Can also be given using the CONTEMPLATE_HTTP_CACHE_DIR environment variable."
+                    })
+                    .value_name("DIR")
+                    .value_hint(ValueHint::DirPath),
+            )
+    }
+
+    #[cfg(feature = "reload-http")]
+    {
+        command = command
+            .arg(
+                Arg::new("on-reload-http")
+                    .long("on-reload-http")
+                    .help("On reload, POST a JSON description of the reload to the given URL")
+                    .long_help(indoc! {
+                        "On reload, POST a JSON description of the reload to the given URL,
+                        e.g. the /-/reload endpoint of Prometheus.
+
+                        The body lists the changed files along with their SHA-256 before and
+                        after the reload, and a timestamp. Responses with a status other than
+                        2xx fail the hook. Credentials given in the URL are sent using basic
+                        authentication.
+
+                        The URL may be followed by any of these options, which only apply to
+                        requests to this URL:
+                          ;header=NAME: VALUE  send the given header, may be given multiple times
+                          ;token-file=PATH     authenticate with the bearer token in the given
+                                               file, read for every request so rotated tokens
+                                               are picked up
+
+                        Example: --on-reload-http 'https://example.com/reload;token-file=/run/token'"
+                    })
+                    .value_name("SPEC")
+                    .value_hint(ValueHint::Url)
+                    .action(ArgAction::Append),
+        )
+    }
+
+    #[cfg(feature = "dbus")]
+    {
+        command = command.arg(
+            Arg::new("on-reload-unit")
+                .long("on-reload-unit")
+                .value_name("UNIT")
+                .help("On reload, reload the given systemd unit, like systemctl reload")
+                .long_help(indoc! {
+                    "On reload, reload the given systemd unit over D-Bus, like systemctl reload,
+                    and wait for the reload to finish. Units without a type are services.
+
+                    Example: 'nginx.service'"
+                })
+                .action(ArgAction::Append),
+        )
+    }
+
     #[cfg(feature = "poll")]
     {
         command = command.arg(
@@ -839,11 +1597,9 @@ impl From<&InPlace> for bool {
     }
 }
 
-#[cfg(feature = "poll")]
 #[derive(Clone)]
 struct HumanDurationParser {}
 
-#[cfg(feature = "poll")]
 impl TypedValueParser for HumanDurationParser {
     type Value = tokio::time::Duration;
 
@@ -861,6 +1617,214 @@ impl TypedValueParser for HumanDurationParser {
     }
 }
 
+/// Parse the interval option of a data source.
+#[cfg(any(feature = "url", feature = "exec"))]
+fn parse_interval(interval: &str) -> Result<std::time::Duration> {
+    humantime::parse_duration(interval)
+        .map_err(|e| Error::CliInvalidSourceOption(format!("invalid interval: {e}")))
+}
+
+/// Options given to a data source argument in the form `ARG;KEY=VALUE;...`.
+#[derive(Debug, Default)]
+struct SourceOptions<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> SourceOptions<'a> {
+    /// Split the options off a data source argument.
+    ///
+    /// Options are taken from the end of the argument, so the argument itself
+    /// may contain semicolons, as long as they are not followed by `KEY=`.
+    fn split(arg: &'a str) -> (&'a str, Self) {
+        let is_key =
+            |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c == '-');
+
+        let mut arg = arg;
+        let mut options = vec![];
+        while let Some((rest, option)) = arg.rsplit_once(';')
+            && let Some((key, value)) = option.split_once('=')
+            && is_key(key)
+        {
+            options.insert(0, (key, value));
+            arg = rest;
+        }
+
+        (arg, Self(options))
+    }
+
+    /// Remove the given option, returning its last value.
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        self.take_all(key).pop()
+    }
+
+    /// Remove the given option, returning all of its values.
+    fn take_all(&mut self, key: &str) -> Vec<&'a str> {
+        let mut values = vec![];
+        self.0.retain(|(k, v)| {
+            if *k == key {
+                values.push(*v);
+            }
+            *k != key
+        });
+        values
+    }
+
+    /// Fail if any options have not been taken.
+    fn finish(self) -> Result<()> {
+        match self.0.first() {
+            Some((key, _)) => Err(Error::CliInvalidSourceOption(format!(
+                "unknown option '{key}'"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Is the given string a valid mount point, with `.` separating nested keys.
+///
+/// Keys consist of alphanumeric characters, `-` and `_`.
+fn is_mount(mount: &str) -> bool {
+    mount.split('.').all(|key| {
+        !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    })
+}
+
+/// Parse a HTTP header given in the form `NAME: VALUE`.
+#[cfg(feature = "reload-http")]
+fn parse_http_header(header: &str) -> std::result::Result<(String, String), String> {
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("header '{header}' is not of the form NAME: VALUE"))?;
+    let (name, value) = (name.trim(), value.trim());
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name '{name}'"))?;
+    HeaderValue::from_str(value).map_err(|_| format!("invalid value of header '{name}'"))?;
+
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+/// Parse a HTTP client profile given in the form `NAME;KEY=VALUE;...`.
+#[cfg(feature = "http")]
+fn parse_http_profile(spec: &str) -> Result<(String, crate::functions::ClientProfile)> {
+    let (name, mut options) = SourceOptions::split(spec);
+    let invalid = |msg: String| Error::CliInvalidHttpProfile(format!("{name}: {msg}"));
+    if name.is_empty() || name.contains(';') {
+        return Err(Error::CliInvalidHttpProfile(format!(
+            "invalid profile name '{name}'"
+        )));
+    }
+
+    let resolve = options
+        .take_all("resolve")
+        .into_iter()
+        .map(|value| {
+            value
+                .split_once(':')
+                .and_then(|(host, addr)| Some((host.to_owned(), addr.parse().ok()?)))
+                .ok_or_else(|| invalid(format!("resolve expects HOST:ADDR, got '{value}'")))
+        })
+        .collect::<Result<_>>()?;
+
+    let insecure = options
+        .take("insecure")
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| invalid(format!("insecure expects true or false, got '{value}'")))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let sni = options.take("sni");
+    if let Some(sni) = sni
+        && (sni.parse::<std::net::IpAddr>().is_ok()
+            || !format!("https://{sni}/")
+                .parse::<reqwest::Url>()
+                .is_ok_and(|url| url.host_str() == Some(&sni.to_ascii_lowercase())))
+    {
+        return Err(invalid(format!("invalid server name '{sni}'")));
+    }
+
+    let profile = crate::functions::ClientProfile {
+        ca: options.take("ca").map(PathBuf::from),
+        cert: options.take("cert").map(PathBuf::from),
+        key: options.take("key").map(PathBuf::from),
+        resolve,
+        sni: sni.map(ToOwned::to_owned),
+        proxy: options.take("proxy").map(ToOwned::to_owned),
+        no_proxy: options.take("no-proxy").map(ToOwned::to_owned),
+        insecure,
+    };
+    options.finish().map_err(|e| match e {
+        Error::CliInvalidSourceOption(msg) => invalid(msg),
+        e => e,
+    })?;
+
+    if profile.key.is_some() && profile.cert.is_none() {
+        return Err(invalid("key given without cert".into()));
+    }
+
+    // the proxy would connect to the server name, rather than the host of the URL.
+    if profile.sni.is_some() && profile.proxy.is_some() {
+        return Err(invalid("sni cannot be combined with proxy".into()));
+    }
+
+    Ok((name.to_owned(), profile))
+}
+
+/// Build an environment data source, taking its normalization options.
+fn environment_from_options(
+    prefix: Option<&str>,
+    options: &mut SourceOptions,
+) -> Result<Environment> {
+    let flag = |options: &mut SourceOptions, name: &str, default: bool| {
+        options
+            .take(name)
+            .map(|value| {
+                value.parse::<bool>().map_err(|_| {
+                    Error::CliInvalidSourceOption(format!(
+                        "{name} expects true or false, got '{value}'"
+                    ))
+                })
+            })
+            .transpose()
+            .map(|value| value.unwrap_or(default))
+    };
+
+    let separator = match options.take("separator") {
+        None => Some(Environment::DEFAULT_SEPARATOR),
+        Some("") => None,
+        separator => separator,
+    };
+    let preserve_case = match options.take("case") {
+        None | Some("lower") => false,
+        Some("preserve") => true,
+        Some(other) => Err(Error::CliInvalidSourceOption(format!(
+            "case must be lower or preserve, got '{other}'"
+        )))?,
+    };
+    let parse = flag(options, "parse", true)?;
+    let allow = options
+        .take_all("allow")
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect();
+    let deny = options
+        .take_all("deny")
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect();
+
+    Ok(Environment::new(prefix)
+        .with_separator(separator)
+        .with_preserve_case(preserve_case)
+        .with_parse(parse)
+        .with_allow(allow)
+        .with_deny(deny))
+}
+
 // Utility function to check whether an iterator has unique elements
 fn elements_are_unique<T>(iter: T) -> bool
 where
@@ -874,7 +1838,6 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
 
     #[test]
     fn test_in_place_suffix() {
@@ -1050,4 +2013,288 @@ mod tests {
         assert!(Cli::new_from(vec!["contemplate", "--watch"]).is_err());
         assert!(Cli::new_from(vec!["contemplate", "--watch", "--template", "-", "-"]).is_err());
     }
+
+    #[test]
+    fn test_is_mount() {
+        assert!(is_mount("db"));
+        assert!(is_mount("services.db-1_a"));
+        assert!(!is_mount(""));
+        assert!(!is_mount("a..b"));
+        assert!(!is_mount("tls@v2"));
+    }
+
+    #[test]
+    #[cfg(feature = "file")]
+    fn test_mounted_sources() {
+        let mut cli = Cli::new_from(vec![
+            "contemplate",
+            "--file",
+            "db.yaml;mount=db",
+            "--file",
+            "config@v2",
+            "--file",
+            "a=b.yaml",
+        ])
+        .unwrap();
+        let sources = cli.sources().unwrap();
+        let mounts: Vec<_> = sources.sources.iter().map(|l| l.mount.as_deref()).collect();
+        assert_eq!(mounts, vec![Some("db"), None, None]);
+        assert!(Cli::new_from(vec!["contemplate", "--file", "db.yaml;mount=a..b"]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "exec")]
+    fn test_mounted_commands() {
+        let mut cli = Cli::new_from(vec![
+            "contemplate",
+            "--exec",
+            r#"X=1 echo "{\"x\": 2}""#,
+            "--exec",
+            r#"echo '{"y": 3}' # ssh user@host;mount=remote"#,
+        ])
+        .unwrap();
+        let sources = cli.sources().unwrap();
+        let mounts: Vec<_> = sources.sources.iter().map(|l| l.mount.as_deref()).collect();
+        assert_eq!(mounts, vec![None, Some("remote")]);
+
+        let context = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(sources.context())
+            .unwrap();
+        assert_eq!(context, serde_json::json!({"x": 2, "remote": {"y": 3}}));
+
+        assert!(Cli::new_from(vec!["contemplate", "--exec", "true;mount=a b"]).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "url", feature = "http"))]
+    fn test_url_profile() {
+        let mut cli = Cli::new_from(vec![
+            "contemplate",
+            "--http-profile",
+            "internal;insecure=true",
+            "--url",
+            "https://user@config.internal/app.json;profile=internal",
+            "--url",
+            "https://config.internal/app.json@v2;mount=app",
+        ])
+        .unwrap();
+        let sources = cli.sources().unwrap();
+        let mounts: Vec<_> = sources.sources.iter().map(|l| l.mount.as_deref()).collect();
+        assert_eq!(mounts, vec![None, Some("app")]);
+
+        assert!(
+            Cli::new_from(vec![
+                "contemplate",
+                "--url",
+                "https://config.internal/app.json;profile=internal",
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "file")]
+    fn test_stdin_source() {
+        let cli = Cli::new_from(vec![
+            "contemplate",
+            "--file",
+            "-;format=yaml",
+            "tpl",
+            "-o",
+            "out",
+        ]);
+        assert!(
+            cli.unwrap().sources().unwrap().sources[0]
+                .source
+                .reads_stdin()
+        );
+
+        // the template is read from standard input as well
+        assert!(Cli::new_from(vec!["contemplate", "--file", "-"]).is_err());
+        assert!(
+            Cli::new_from(vec!["contemplate", "--file", "-", "--template", "-", "out"]).is_err()
+        );
+        assert!(Cli::new_from(vec!["contemplate", "--file", "-", "--file", "-", "tpl"]).is_err());
+        assert!(Cli::new_from(vec!["contemplate", "--file", "-", "--dump-context"]).is_ok());
+    }
+
+    #[test]
+    fn test_source_options() {
+        let (arg, mut options) = SourceOptions::split("defaults.yaml;merge=join");
+        assert_eq!(arg, "defaults.yaml");
+        assert_eq!(options.take("merge"), Some("join"));
+        assert!(options.finish().is_ok());
+
+        let (arg, options) = SourceOptions::split("sh -c 'a; b'");
+        assert_eq!(arg, "sh -c 'a; b'");
+        assert!(options.finish().is_ok());
+
+        let (arg, mut options) = SourceOptions::split("x;merge=join;bogus=1");
+        assert_eq!(arg, "x");
+        assert_eq!(options.take("merge"), Some("join"));
+        assert!(options.finish().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_http_profile() {
+        let (name, profile) = parse_http_profile(
+            "internal;ca=/ca.pem;cert=/c.pem;key=/c.key;resolve=api:10.0.0.1:443;insecure=true",
+        )
+        .unwrap();
+        assert_eq!(name, "internal");
+        assert_eq!(profile.ca, Some("/ca.pem".into()));
+        assert_eq!(profile.key, Some("/c.key".into()));
+        assert_eq!(
+            profile.resolve,
+            vec![("api".to_string(), "10.0.0.1:443".parse().unwrap())]
+        );
+        assert!(profile.insecure);
+
+        assert!(parse_http_profile("internal;resolve=api").is_err());
+        assert!(parse_http_profile("internal;key=/c.key").is_err());
+        assert!(parse_http_profile("internal;bogus=1").is_err());
+
+        let (_, profile) = parse_http_profile("internal;sni=Config.internal").unwrap();
+        assert_eq!(profile.sni.as_deref(), Some("Config.internal"));
+        assert!(parse_http_profile("internal;sni=10.0.0.1").is_err());
+        assert!(parse_http_profile("internal;sni=config/internal").is_err());
+        assert!(parse_http_profile("internal;sni=c.internal;proxy=http://proxy:3128").is_err());
+
+        let cli = |profiles: &[&str]| {
+            let args = profiles
+                .iter()
+                .flat_map(|profile| ["--http-profile", profile]);
+            Cli::new_from(std::iter::once("contemplate").chain(args))
+        };
+        assert!(cli(&["internal", "default;insecure=true"]).is_ok());
+        assert!(cli(&["internal", "internal;insecure=true"]).is_err());
+        assert!(cli(&["internal;ca=/nonexistent/ca.pem"]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "file")]
+    fn test_merge_strategy_option() {
+        let mut cli = Cli::new_from(vec![
+            "contemplate",
+            "--file",
+            "db.yaml;mount=db;merge=adjoin",
+            "--file",
+            "defaults.yaml;merge=join",
+        ])
+        .unwrap();
+        let sources = cli.sources().unwrap();
+        let layers: Vec<_> = sources
+            .sources
+            .iter()
+            .map(|l| (l.mount.as_deref(), l.strategy))
+            .collect();
+        assert_eq!(
+            layers,
+            vec![
+                (Some("db"), MergeStrategy::Adjoin),
+                (None, MergeStrategy::Join)
+            ]
+        );
+
+        assert!(Cli::new_from(vec!["contemplate", "--file", "x.yaml;merge=bogus"]).is_err());
+        assert!(Cli::new_from(vec!["contemplate", "--file", "x.yaml;bogus=1"]).is_err());
+    }
+
+    #[test]
+    fn test_on_reload_order() {
+        let cli = Cli::new_from(vec![
+            "contemplate",
+            "--on-reload-exec",
+            "/bin/validate",
+            "-q",
+            ";",
+            "--on-reload-signal",
+            "HUP",
+            "nginx",
+            "--on-reload-command",
+            "true",
+            "--on-reload-signal",
+            "USR1",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.on_reload().unwrap(),
+            vec![
+                OnReloadAction::Executable {
+                    path: "/bin/validate".into(),
+                    args: vec!["-q".into()],
+                },
+                OnReloadAction::Signal {
+                    signal: nix::sys::signal::SIGHUP,
+                    target: OnReloadSignalTarget::ProcessName("nginx".into()),
+                },
+                OnReloadAction::ShellCommand("true".into()),
+                OnReloadAction::Signal {
+                    signal: nix::sys::signal::SIGUSR1,
+                    target: Default::default(),
+                },
+            ]
+        );
+        assert!(!cli.hook_policy().keep_going);
+    }
+
+    #[cfg(feature = "reload-http")]
+    #[test]
+    fn test_on_reload_http() {
+        let cli = Cli::new_from(vec![
+            "contemplate",
+            "--on-reload-http",
+            "http://localhost:9090/-/reload;header=X-Scope: a;header=X-Other:1",
+            "--on-reload-http",
+            "https://example.com/hooks;token-file=/run/token",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.on_reload().unwrap(),
+            vec![
+                OnReloadAction::Http(crate::reload::HttpTarget {
+                    url: "http://localhost:9090/-/reload".parse().unwrap(),
+                    headers: vec![
+                        ("X-Scope".into(), "a".into()),
+                        ("X-Other".into(), "1".into()),
+                    ],
+                    token_file: None,
+                }),
+                OnReloadAction::Http(crate::reload::HttpTarget {
+                    url: "https://example.com/hooks".parse().unwrap(),
+                    headers: vec![],
+                    token_file: Some("/run/token".into()),
+                }),
+            ]
+        );
+
+        let on_reload = |spec| {
+            Cli::new_from(vec!["contemplate", "--on-reload-http", spec])
+                .unwrap()
+                .on_reload()
+        };
+        assert!(on_reload("http://localhost/;header=X-Scope").is_err());
+        assert!(on_reload("http://localhost/;bogus=1").is_err());
+        assert!(on_reload("localhost;token-file=/run/token").is_err());
+    }
+
+    #[test]
+    fn test_dump_context_args() {
+        let cli = Cli::new_from(vec!["contemplate"]).unwrap();
+        assert_eq!(cli.dump_context(), None);
+
+        let cli = Cli::new_from(vec!["contemplate", "--dump-context"]).unwrap();
+        assert_eq!(cli.dump_context(), Some(DumpFormat::Json));
+        assert!(!cli.explain());
+
+        let cli =
+            Cli::new_from(vec!["contemplate", "--dump-context", "json", "--explain"]).unwrap();
+        assert_eq!(cli.dump_context(), Some(DumpFormat::Json));
+        assert!(cli.explain());
+
+        assert!(Cli::new_from(vec!["contemplate", "--explain"]).is_err());
+        assert!(Cli::new_from(vec!["contemplate", "--dump-context", "xml"]).is_err());
+    }
 }
@@ -0,0 +1,187 @@
+use std::fmt::Display;
+#[cfg(feature = "file")]
+use std::path::Path;
+use std::str::FromStr;
+
+use figment::{
+    Figment, Metadata, Profile, Provider,
+    providers::{Format, Json, Toml, YamlExtended},
+    value::{Dict, Map},
+};
+
+#[cfg(feature = "file")]
+use super::formats::{Dotenv, Ini, Json5, Properties, Ron};
+use crate::error::Error;
+
+/// The format of a document read by a data source.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DocumentFormat {
+    Json,
+    Yaml,
+    Toml,
+    #[cfg(feature = "file")]
+    Json5,
+    #[cfg(feature = "file")]
+    Ron,
+    #[cfg(feature = "file")]
+    Ini,
+    #[cfg(feature = "file")]
+    Properties,
+    #[cfg(feature = "file")]
+    Dotenv,
+}
+
+impl DocumentFormat {
+    /// Determine the format from a file extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            #[cfg(feature = "file")]
+            "json5" => Some(Self::Json5),
+            #[cfg(feature = "file")]
+            "ron" => Some(Self::Ron),
+            #[cfg(feature = "file")]
+            "ini" => Some(Self::Ini),
+            #[cfg(feature = "file")]
+            "properties" => Some(Self::Properties),
+            #[cfg(feature = "file")]
+            "env" => Some(Self::Dotenv),
+            _ => None,
+        }
+    }
+
+    /// Determine the format from a file name, like `config.yaml` or `.env.local`.
+    #[cfg(feature = "file")]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+        if name == ".env" || name.starts_with(".env.") {
+            return Some(Self::Dotenv);
+        }
+        Self::from_extension(&path.extension()?.to_string_lossy())
+    }
+
+    /// Determine the format from a MIME type like `application/json`.
+    #[cfg(feature = "url")]
+    pub fn from_mime(mime: &mime::Mime) -> Option<Self> {
+        let suffix = mime.suffix().map(|s| s.as_str());
+        match (mime.subtype().as_str(), suffix) {
+            ("json", _) | (_, Some("json")) => Some(Self::Json),
+            ("yaml" | "x-yaml", _) | (_, Some("yaml")) => Some(Self::Yaml),
+            ("toml", _) => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Merge a document into the given [Figment], attributing its values to `kind` and `name`.
+    pub fn merge(self, figment: Figment, kind: &str, name: &str, document: &str) -> Figment {
+        match self {
+            Self::Json => figment.merge(Named::new(kind, name, Json::string(document))),
+            Self::Yaml => figment.merge(Named::new(kind, name, YamlExtended::string(document))),
+            Self::Toml => figment.merge(Named::new(kind, name, Toml::string(document))),
+            #[cfg(feature = "file")]
+            Self::Json5 => figment.merge(Named::new(kind, name, Json5::string(document))),
+            #[cfg(feature = "file")]
+            Self::Ron => figment.merge(Named::new(kind, name, Ron::string(document))),
+            #[cfg(feature = "file")]
+            Self::Ini => figment.merge(Named::new(kind, name, Ini::string(document))),
+            #[cfg(feature = "file")]
+            Self::Properties => figment.merge(Named::new(kind, name, Properties::string(document))),
+            #[cfg(feature = "file")]
+            Self::Dotenv => figment.merge(Named::new(kind, name, Dotenv::string(document))),
+        }
+    }
+
+    /// Merge the file at `path` into the given [Figment].
+    #[cfg(feature = "file")]
+    pub fn merge_file(self, figment: Figment, path: &Path) -> Figment {
+        match self {
+            Self::Json => figment.merge(Json::file(path)),
+            Self::Yaml => figment.merge(YamlExtended::file(path)),
+            Self::Toml => figment.merge(Toml::file(path)),
+            Self::Json5 => figment.merge(Json5::file(path)),
+            Self::Ron => figment.merge(Ron::file(path)),
+            Self::Ini => figment.merge(Ini::file(path)),
+            Self::Properties => figment.merge(Properties::file(path)),
+            Self::Dotenv => figment.merge(Dotenv::file(path)),
+        }
+    }
+}
+
+impl FromStr for DocumentFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "yaml" => Ok(Self::Yaml),
+            "toml" => Ok(Self::Toml),
+            #[cfg(feature = "file")]
+            "json5" => Ok(Self::Json5),
+            #[cfg(feature = "file")]
+            "ron" => Ok(Self::Ron),
+            #[cfg(feature = "file")]
+            "ini" => Ok(Self::Ini),
+            #[cfg(feature = "file")]
+            "properties" => Ok(Self::Properties),
+            #[cfg(feature = "file")]
+            "dotenv" | "env" => Ok(Self::Dotenv),
+            other => Err(Error::CliInvalidSourceOption(format!(
+                "unknown format '{other}'"
+            ))),
+        }
+    }
+}
+
+impl Display for DocumentFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Yaml => write!(f, "yaml"),
+            Self::Toml => write!(f, "toml"),
+            #[cfg(feature = "file")]
+            Self::Json5 => write!(f, "json5"),
+            #[cfg(feature = "file")]
+            Self::Ron => write!(f, "ron"),
+            #[cfg(feature = "file")]
+            Self::Ini => write!(f, "ini"),
+            #[cfg(feature = "file")]
+            Self::Properties => write!(f, "properties"),
+            #[cfg(feature = "file")]
+            Self::Dotenv => write!(f, "dotenv"),
+        }
+    }
+}
+
+/// A provider attributing its values to the data source they were read from.
+struct Named<P> {
+    kind: String,
+    name: String,
+    provider: P,
+}
+
+impl<P> Named<P> {
+    fn new(kind: &str, name: &str, provider: P) -> Self {
+        let kind = kind.to_owned();
+        let name = name.to_owned();
+        Self {
+            kind,
+            name,
+            provider,
+        }
+    }
+}
+
+impl<P: Provider> Provider for Named<P> {
+    fn metadata(&self) -> Metadata {
+        Metadata::from(
+            self.kind.clone(),
+            figment::Source::Custom(self.name.clone()),
+        )
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, figment::Error> {
+        self.provider.data()
+    }
+}
@@ -1,17 +1,196 @@
 use super::Source;
 use crate::{datasource::Result, watch::Watch};
 use async_trait::async_trait;
-use figment::{Figment, providers::Env};
+use figment::{
+    Figment, Metadata, Profile, Provider,
+    util::nest,
+    value::{Dict, Map, Value},
+};
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct Environment {
     prefix: Option<String>,
+
+    /// The separator nesting keys, or `None` to keep keys flat.
+    separator: Option<String>,
+
+    /// Whether to keep the case of keys, rather than lowercasing them.
+    preserve_case: bool,
+
+    /// Whether to parse values into structured values, rather than keeping strings.
+    parse: bool,
+
+    /// Patterns of variable names to take, if not empty.
+    allow: Vec<String>,
+
+    /// Patterns of variable names to ignore.
+    deny: Vec<String>,
 }
 
 impl Environment {
+    pub const DEFAULT_SEPARATOR: &str = "_";
+
     pub fn new<S: AsRef<str>>(prefix: Option<S>) -> Self {
         let prefix = prefix.map(|s| s.as_ref().to_owned());
-        Self { prefix }
+        Self {
+            prefix,
+            separator: Some(Self::DEFAULT_SEPARATOR.to_owned()),
+            preserve_case: false,
+            parse: true,
+            allow: vec![],
+            deny: vec![],
+        }
+    }
+
+    /// Nest keys at the given separator, or keep them flat if `None`.
+    pub fn with_separator<S: AsRef<str>>(mut self, separator: Option<S>) -> Self {
+        self.separator = separator.map(|s| s.as_ref().to_owned());
+        self
+    }
+
+    /// Keep the case of keys, rather than lowercasing them.
+    pub fn with_preserve_case(mut self, preserve_case: bool) -> Self {
+        self.preserve_case = preserve_case;
+        self
+    }
+
+    /// Parse values into booleans, numbers, lists and dictionaries, rather than keeping strings.
+    pub fn with_parse(mut self, parse: bool) -> Self {
+        self.parse = parse;
+        self
+    }
+
+    /// Only take variables matching any of the patterns, unless the list is empty.
+    ///
+    /// Patterns match variable names including the prefix, ignoring case.
+    /// A trailing `*` matches any suffix.
+    pub fn with_allow(mut self, allow: Vec<String>) -> Self {
+        self.allow = allow;
+        self
+    }
+
+    /// Ignore variables matching any of the patterns.
+    pub fn with_deny(mut self, deny: Vec<String>) -> Self {
+        self.deny = deny;
+        self
+    }
+
+    /// The prefix variable names must start with, including the separator.
+    fn full_prefix(&self) -> Option<String> {
+        let separator = self.separator.as_deref().unwrap_or(Self::DEFAULT_SEPARATOR);
+        self.prefix
+            .as_ref()
+            .map(|prefix| format!("{prefix}{separator}"))
+    }
+
+    /// Whether the variable is selected by the allow and deny lists.
+    fn is_selected(&self, name: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => name
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+            None => name.eq_ignore_ascii_case(pattern),
+        };
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+
+    /// Normalize a variable name to a (dotted) key, or `None` if it isn't taken.
+    fn key(&self, name: &str) -> Option<String> {
+        if !self.is_selected(name) {
+            return None;
+        }
+
+        let key = match self.full_prefix() {
+            Some(prefix) => name
+                .get(..prefix.len())
+                .filter(|start| start.eq_ignore_ascii_case(&prefix))
+                .map(|_| &name[prefix.len()..])?,
+            None => name,
+        };
+        if key.is_empty() {
+            return None;
+        }
+
+        let key = match self.separator {
+            Some(ref separator) if !separator.is_empty() => key.replace(separator, "."),
+            _ => key.to_owned(),
+        };
+        match self.preserve_case {
+            true => Some(key),
+            false => Some(key.to_ascii_lowercase()),
+        }
+    }
+
+    /// A provider of the given variables, normalized like the process environment.
+    pub fn provider<I>(&self, name: &str, vars: I) -> Variables
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut dict = Dict::new();
+        for (key, value) in vars
+            .into_iter()
+            .filter_map(|(name, value)| Some((self.key(&name)?, value)))
+        {
+            let value = match self.parse {
+                true => value.parse().expect("infallible"),
+                false => Value::from(value),
+            };
+            if let Some(nested) = nest(&key, value).into_dict() {
+                merge_dicts(&mut dict, nested);
+            }
+        }
+
+        let name = match self.full_prefix() {
+            Some(prefix) => format!("`{}` {name}", prefix.to_ascii_uppercase()),
+            None => name.to_owned(),
+        };
+        let separator = self.separator.clone().unwrap_or_default();
+        let metadata = Metadata::named(name).interpolater(move |_: &Profile, k: &[&str]| {
+            k.iter()
+                .map(|k| k.to_ascii_uppercase())
+                .collect::<Vec<_>>()
+                .join(&separator)
+        });
+
+        Variables { metadata, dict }
+    }
+}
+
+/// Merge `other` into `dict` recursively, with values of `other` taking precedence.
+fn merge_dicts(dict: &mut Dict, other: Dict) {
+    for (key, value) in other {
+        match (dict.get_mut(&key), value) {
+            (Some(Value::Dict(_, existing)), Value::Dict(_, nested)) => {
+                merge_dicts(existing, nested)
+            }
+            (_, value) => {
+                dict.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Normalized variables, from the environment or another source.
+pub struct Variables {
+    metadata: Metadata,
+    dict: Dict,
+}
+
+impl Variables {
+    /// Attribute the variables to the given source, like the file they were read from.
+    pub fn with_source(mut self, source: figment::Source) -> Self {
+        self.metadata = self.metadata.source(source);
+        self
+    }
+}
+
+impl Provider for Variables {
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn data(&self) -> std::result::Result<Map<Profile, Dict>, figment::Error> {
+        Ok(Profile::Default.collect(self.dict.clone()))
     }
 }
 
@@ -20,10 +199,53 @@ impl Watch for Environment {}
 #[async_trait]
 impl Source for Environment {
     async fn merge_to_figment(&self, figment: Figment) -> Result<Figment> {
-        let env = match self.prefix {
-            Some(ref prefix) => Env::prefixed(&format!("{prefix}_")),
-            None => Env::raw(),
-        };
-        Ok(figment.merge(env.split("_")))
+        let vars = std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        Ok(figment.merge(self.provider("environment variable(s)", vars)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn extract(env: &Environment, vars: &[(&str, &str)]) -> serde_json::Value {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        Figment::from(env.provider("test", vars)).extract().unwrap()
+    }
+
+    #[test]
+    fn test_normalization() {
+        let vars = [
+            ("APP_DB__HOST_NAME", "db"),
+            ("APP_PORT", "80"),
+            ("OTHER", "x"),
+        ];
+
+        let env = Environment::new(Some("app"));
+        assert_eq!(
+            extract(
+                &env,
+                &[("APP_DB_HOST", "db"), ("APP_PORT", "80"), ("OTHER", "x")]
+            ),
+            json!({ "db": { "host": "db" }, "port": 80 })
+        );
+
+        let env = Environment::new(None::<&str>)
+            .with_separator(Some("__"))
+            .with_preserve_case(true)
+            .with_parse(false);
+        assert_eq!(
+            extract(&env, &vars),
+            json!({ "APP_DB": { "HOST_NAME": "db" }, "APP_PORT": "80", "OTHER": "x" })
+        );
+
+        let env = Environment::new(None::<&str>)
+            .with_separator(None::<&str>)
+            .with_allow(vec!["app_*".into()])
+            .with_deny(vec!["APP_PORT".into()]);
+        assert_eq!(extract(&env, &vars), json!({ "app_db__host_name": "db" }));
     }
 }
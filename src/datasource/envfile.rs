@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use figment::Figment;
+use notify::RecommendedWatcher;
+
+use super::{Environment, Notifier, Source, ToDataSourceError, watch_file};
+use crate::{error::Error, watch::Watch};
+
+/// Environment variables read from a file of `KEY=VALUE` lines, like a mounted `app.env`.
+///
+/// Unlike the process environment, the file is watched for changes.
+pub struct EnvFile {
+    path: PathBuf,
+    env: Environment,
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl EnvFile {
+    /// Read variables from the file at `path`, normalized like the given environment.
+    pub fn new<P: AsRef<Path>>(path: P, env: Environment) -> Self {
+        let path = path.as_ref().to_owned();
+        Self {
+            path,
+            env,
+            watcher: None,
+        }
+    }
+
+    fn read(&self) -> Result<Vec<(String, String)>, Error> {
+        dotenvy::from_path_iter(&self.path)
+            .and_then(Iterator::collect)
+            .map_err(|e| match e {
+                dotenvy::Error::Io(e) => Error::IOError(e),
+                e => Error::InvalidEnvFile(self.path.clone(), e.to_string()),
+            })
+    }
+}
+
+#[async_trait]
+impl Source for EnvFile {
+    async fn merge_to_figment(&self, figment: Figment) -> super::Result<Figment> {
+        let vars = match self.read() {
+            Err(e @ Error::IOError(_)) => return Err(e).recoverable(),
+            vars => vars.fatal()?,
+        };
+
+        let provider = self
+            .env
+            .provider("env file", vars)
+            .with_source(figment::Source::File(self.path.clone()));
+        Ok(figment.merge(provider))
+    }
+}
+
+#[async_trait]
+impl Watch for EnvFile {
+    async fn watch(&mut self, notify: Notifier) {
+        self.watcher = watch_file(&self.path, notify, format!("{:?}", *self));
+    }
+}
+
+impl std::fmt::Debug for EnvFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnvFile").field("path", &self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_env_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            &file,
+            "# comment\nexport APP_DB_HOST=db\nAPP_PORT='80'\nOTHER=1\n",
+        )
+        .unwrap();
+
+        let source = EnvFile::new(file.path(), Environment::new(Some("APP")));
+        let figment = source.merge_to_figment(Figment::new()).await.ok();
+        assert_eq!(
+            figment.unwrap().extract::<serde_json::Value>().unwrap(),
+            serde_json::json!({ "db": { "host": "db" }, "port": 80 })
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use figment::Figment;
+use tokio::process::Command;
+
+use super::{DataSourceError, DocumentFormat, Notifier, Source};
+use crate::{error::Error, watch::Watch};
+
+/// A data source taking values from the output of a shell command.
+pub struct Exec {
+    command: String,
+    format: DocumentFormat,
+
+    /// Whether a failing command prevents rendering, rather than being skipped.
+    fatal: bool,
+
+    /// The interval the command is re-run in, when watching.
+    interval: Option<Duration>,
+
+    /// The output of the last run, shared with the watcher to detect changes.
+    last: Arc<Mutex<Option<String>>>,
+}
+
+impl Exec {
+    pub fn new<S: AsRef<str>>(command: S, format: DocumentFormat) -> Self {
+        Self {
+            command: command.as_ref().to_owned(),
+            format,
+            fatal: false,
+            interval: None,
+            last: Default::default(),
+        }
+    }
+
+    /// Fail instead of skipping the data source if the command fails.
+    pub fn with_fatal(mut self, fatal: bool) -> Self {
+        self.fatal = fatal;
+        self
+    }
+
+    /// Re-run the command in the given interval when watching.
+    pub fn with_interval(mut self, interval: Option<Duration>) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+/// Run the command, returning its standard output.
+async fn run(command: &str) -> Result<String, Error> {
+    let output = crate::supervise::spawn(
+        Command::new("/bin/sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?
+    .wait_with_output()
+    .await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            command: command.to_owned(),
+            status: output.status,
+            stderr: stderr.trim().to_owned(),
+        });
+    }
+    if !stderr.trim().is_empty() {
+        log::debug!("{command:?} wrote to stderr: {}", stderr.trim());
+    }
+
+    String::from_utf8(output.stdout)
+        .map_err(|e| Error::IOError(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+#[async_trait]
+impl Source for Exec {
+    async fn merge_to_figment(&self, figment: Figment) -> super::Result<Figment> {
+        let output = run(&self.command).await.map_err(|e| match e {
+            e @ Error::CommandFailed { .. } if !self.fatal => DataSourceError::recoverable(e),
+            e => DataSourceError::fatal(e),
+        })?;
+
+        self.last
+            .lock()
+            .expect("lock poisoned")
+            .replace(output.clone());
+
+        Ok(self.format.merge(figment, "exec", &self.command, &output))
+    }
+}
+
+#[async_trait]
+impl Watch for Exec {
+    async fn watch(&mut self, notify: Notifier) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let self_dbg = format!("{:?}", *self);
+        let command = self.command.clone();
+        let last = self.last.clone();
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        tokio::spawn(async move {
+            // The first tick completes immediately, while the command just ran.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let output = match run(&command).await {
+                    Ok(output) => output,
+                    Err(e) => {
+                        log::warn!("{e}");
+                        continue;
+                    }
+                };
+
+                let changed = last.lock().expect("lock poisoned").as_ref() != Some(&output);
+                if changed {
+                    notify.notify_async(&self_dbg).await;
+                }
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for Exec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Exec")
+            .field("command", &self.command)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_exec() {
+        let source = Exec::new(r#"echo '{"a": 1}'"#, DocumentFormat::Json);
+        let figment = source.merge_to_figment(Figment::new()).await.ok().unwrap();
+        assert_eq!(figment.extract_inner::<i32>("a").unwrap(), 1);
+
+        let source = Exec::new("printf 'a: 2'", DocumentFormat::Yaml);
+        let figment = source.merge_to_figment(Figment::new()).await.ok().unwrap();
+        assert_eq!(figment.extract_inner::<i32>("a").unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failing_command() {
+        let source = Exec::new("exit 3", DocumentFormat::Json);
+        let result = source.merge_to_figment(Figment::new()).await;
+        assert!(result.is_err_and(|e| e.is_recoverable()));
+
+        let source = source.with_fatal(true);
+        let result = source.merge_to_figment(Figment::new()).await;
+        assert!(result.is_err_and(|e| e.is_fatal()));
+    }
+}
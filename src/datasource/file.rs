@@ -1,19 +1,17 @@
-use super::{Notifier, Source};
+use super::{DocumentFormat, Notifier, Source, watch_file};
 use crate::datasource::{Result, ToDataSourceError};
 use crate::error::Error;
 use crate::watch::Watch;
 use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
-use figment::{
-    Figment,
-    providers::{Format, Json, Toml, YamlExtended},
-};
+use figment::Figment;
 
-use notify::{Config, EventKind, RecommendedWatcher, Watcher};
+use notify::RecommendedWatcher;
 
 pub struct File {
     path: PathBuf,
+    format: Option<DocumentFormat>,
     watcher: Option<RecommendedWatcher>,
 }
 
@@ -22,70 +20,50 @@ impl File {
         let path = path.as_ref().to_owned();
         Self {
             path,
+            format: None,
             watcher: None,
         }
     }
+
+    /// Use the given format instead of guessing it from the file name.
+    pub fn with_format(mut self, format: Option<DocumentFormat>) -> Self {
+        self.format = format;
+        self
+    }
 }
 
 #[async_trait]
 impl Source for File {
     async fn merge_to_figment(&self, figment: Figment) -> Result<Figment> {
-        let figment = match self
-            .path
-            .extension()
-            .map(|s| s.to_string_lossy().to_lowercase())
-            .as_deref()
+        let format = match self
+            .format
+            .or_else(|| DocumentFormat::from_path(&self.path))
         {
-            Some("json") => figment.merge(Json::file(&self.path)),
-            Some("toml") => figment.merge(Toml::file(&self.path)),
-            Some("yaml") | Some("yml") => figment.merge(YamlExtended::file(&self.path)),
-            Some(ext) => {
-                return Err(Error::UnknownFileExtension {
-                    ext: ext.to_owned(),
-                })
-                .fatal();
-            }
-            None => {
-                return Err(Error::UnknownFileType {
-                    path: self.path.to_owned(),
-                })
-                .fatal();
-            }
+            Some(format) => format,
+            None => match self.path.extension() {
+                Some(ext) => {
+                    return Err(Error::UnknownFileExtension {
+                        ext: ext.to_string_lossy().to_lowercase(),
+                    })
+                    .fatal();
+                }
+                None => {
+                    return Err(Error::UnknownFileType {
+                        path: self.path.to_owned(),
+                    })
+                    .fatal();
+                }
+            },
         };
 
-        Ok(figment)
+        Ok(format.merge_file(figment, &self.path))
     }
 }
 
 #[async_trait]
 impl Watch for File {
     async fn watch(&mut self, notify: Notifier) {
-        let self_dbg = format!("{:?}", *self);
-        let Ok(mut watcher) = RecommendedWatcher::new(
-            move |evt: std::result::Result<notify::Event, notify::Error>| match evt {
-                Ok(e) => {
-                    if matches!(
-                        e.kind,
-                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
-                    ) {
-                        notify.notify(&self_dbg);
-                    }
-                }
-                Err(e) => {
-                    log::warn!("Notify error: {e}")
-                }
-            },
-            Config::default(),
-        ) else {
-            log::error!("Could not create notifier for {:?}", self.path);
-            return;
-        };
-
-        if let Err(e) = watcher.watch(&self.path, notify::RecursiveMode::NonRecursive) {
-            log::error!("Could not register notifier for {:?}: {e}", self.path)
-        }
-
-        self.watcher.replace(watcher);
+        self.watcher = watch_file(&self.path, notify, format!("{:?}", *self));
     }
 }
 
@@ -0,0 +1,217 @@
+//! [Format] implementations for the document formats figment doesn't support natively.
+
+use figment::providers::Format;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+fn error<E: std::fmt::Display>(e: E) -> figment::Error {
+    figment::Error::from(e.to_string())
+}
+
+/// JSON5, a superset of JSON allowing comments, trailing commas and unquoted keys.
+pub struct Json5;
+
+impl Format for Json5 {
+    type Error = figment::Error;
+    const NAME: &'static str = "JSON5";
+
+    fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, Self::Error> {
+        json5::from_str(string).map_err(error)
+    }
+}
+
+/// Rusty Object Notation.
+pub struct Ron;
+
+impl Format for Ron {
+    type Error = figment::Error;
+    const NAME: &'static str = "RON";
+
+    fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, Self::Error> {
+        ron::from_str(string).map_err(error)
+    }
+}
+
+/// INI files. Keys outside of a section are top-level, sections become dictionaries.
+pub struct Ini;
+
+impl Format for Ini {
+    type Error = figment::Error;
+    const NAME: &'static str = "INI";
+
+    fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, Self::Error> {
+        let ini = ini::Ini::load_from_str(string).map_err(error)?;
+
+        let mut map = Map::new();
+        for (section, properties) in ini.iter() {
+            let entries = properties
+                .iter()
+                .map(|(k, v)| (k.to_owned(), Value::from(v)));
+            match section {
+                None => map.extend(entries),
+                Some(section) => {
+                    let Value::Object(dict) = map
+                        .entry(section)
+                        .or_insert_with(|| Value::Object(Map::new()))
+                    else {
+                        return Err(error(format!(
+                            "section [{section}] conflicts with a key of the same name"
+                        )));
+                    };
+                    dict.extend(entries);
+                }
+            }
+        }
+
+        serde_json::from_value(map.into()).map_err(error)
+    }
+}
+
+/// Java properties files. Keys are taken verbatim, including any dots.
+pub struct Properties;
+
+impl Format for Properties {
+    type Error = figment::Error;
+    const NAME: &'static str = "properties";
+
+    fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, Self::Error> {
+        let map = parse_properties(string)
+            .into_iter()
+            .map(|(k, v)| (k, Value::from(v)))
+            .collect::<Map<_, _>>();
+        serde_json::from_value(map.into()).map_err(error)
+    }
+}
+
+/// Dotenv files, supporting quoting, `export` and variable substitution. All values are strings.
+pub struct Dotenv;
+
+impl Format for Dotenv {
+    type Error = figment::Error;
+    const NAME: &'static str = "dotenv";
+
+    fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, Self::Error> {
+        let map = dotenvy::from_read_iter(string.as_bytes())
+            .map(|entry| entry.map(|(k, v)| (k, Value::from(v))))
+            .collect::<Result<Map<_, _>, _>>()
+            .map_err(error)?;
+        serde_json::from_value(map.into()).map_err(error)
+    }
+}
+
+/// Parse a properties file into its key-value pairs.
+fn parse_properties(string: &str) -> Vec<(String, String)> {
+    let mut properties = vec![];
+    let mut lines = string.lines();
+
+    while let Some(line) = lines.next() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+
+        // Join continuation lines, which end in an odd number of backslashes.
+        let mut logical = line.to_owned();
+        while logical.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1 {
+            logical.pop();
+            match lines.next() {
+                Some(next) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let mut key = String::new();
+        let mut chars = logical.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => key.extend(chars.next().map(unescape)),
+                '=' | ':' => break,
+                c if c.is_whitespace() => {
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    chars.next_if(|c| *c == '=' || *c == ':');
+                    break;
+                }
+                c => key.push(c),
+            }
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('u') => {
+                        let hex = chars.by_ref().take(4).collect::<String>();
+                        value.extend(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32));
+                    }
+                    escaped => value.extend(escaped.map(unescape)),
+                },
+                c => value.push(c),
+            }
+        }
+
+        properties.push((key, value));
+    }
+
+    properties
+}
+
+fn unescape(c: char) -> char {
+    match c {
+        't' => '\t',
+        'n' => '\n',
+        'r' => '\r',
+        'f' => '\x0c',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_properties() {
+        let properties = parse_properties(indoc::indoc! {r#"
+            # comment
+            ! also a comment
+            db.host = localhost
+            db.port:5432
+            greeting   Hello \
+                       World
+            path=C:\\temp
+            key\ with\ spaces = tab\there \u00e9
+        "#});
+        assert_eq!(
+            properties,
+            [
+                ("db.host", "localhost"),
+                ("db.port", "5432"),
+                ("greeting", "Hello World"),
+                ("path", "C:\\temp"),
+                ("key with spaces", "tab\there é"),
+            ]
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_formats() {
+        let ini: Value = Ini::from_str("a = 1\n[db]\nhost = x\n").unwrap();
+        assert_eq!(ini, json!({ "a": "1", "db": { "host": "x" } }));
+
+        let dotenv: Value =
+            Dotenv::from_str("# comment\nexport A=1\nB=\"two words\"\nC='$A'\nD=${A}2\n").unwrap();
+        assert_eq!(
+            dotenv,
+            json!({ "A": "1", "B": "two words", "C": "$A", "D": "12" })
+        );
+
+        let json5: Value = Json5::from_str("{ a: 1, // comment\n b: [true,], }").unwrap();
+        assert_eq!(json5, json!({ "a": 1, "b": [true] }));
+
+        let ron: Value = Ron::from_str(r#"(a: 1, b: "x")"#).unwrap();
+        assert_eq!(ron, json!({ "a": 1, "b": "x" }));
+    }
+}
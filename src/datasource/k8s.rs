@@ -47,11 +47,11 @@ impl Source for ConfigMap {
             return Err(Error::ConfigMapDoesNotExist(self.name.clone())).recoverable();
         };
 
-        let data: ConfigMapProvider = cm
+        let data = cm
             .data
             .ok_or_else(|| Error::ConfigMapDoesNotExist(self.name.clone()))
-            .recoverable()?
-            .into();
+            .recoverable()?;
+        let data = ConfigMapProvider::new(self.to_string(), data);
         let figment = figment.merge(data);
         Ok(figment)
     }
@@ -93,23 +93,35 @@ impl Watch for ConfigMap {
     }
 }
 
+impl std::fmt::Display for ConfigMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.namespace {
+            Some(ref ns) => write!(f, "{ns}/{}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
 struct ConfigMapProvider {
+    name: String,
     data: BTreeMap<String, String>,
 }
 
-impl From<BTreeMap<String, String>> for ConfigMapProvider {
-    fn from(value: BTreeMap<String, String>) -> Self {
-        Self { data: value }
+impl ConfigMapProvider {
+    fn new(name: String, data: BTreeMap<String, String>) -> Self {
+        Self { name, data }
     }
 }
 
 impl Provider for ConfigMapProvider {
     fn metadata(&self) -> figment::Metadata {
-        Metadata::named("k8s configmap").interpolater(move |_: &Profile, k: &[&str]| {
-            let keys: Vec<_> = k.iter().map(|k| k.to_ascii_uppercase()).collect();
+        Metadata::from("k8s configmap", figment::Source::Custom(self.name.clone())).interpolater(
+            move |_: &Profile, k: &[&str]| {
+                let keys: Vec<_> = k.iter().map(|k| k.to_ascii_uppercase()).collect();
 
-            keys.join(".")
-        })
+                keys.join(".")
+            },
+        )
     }
 
     fn data(
@@ -161,11 +173,11 @@ impl Source for Secret {
             return Err(Error::SecretDoesNotExist(self.name.clone())).recoverable();
         };
 
-        let data: SecretProvider = secret
+        let data = secret
             .data
             .ok_or_else(|| Error::SecretDoesNotExist(self.name.clone()))
-            .recoverable()?
-            .into();
+            .recoverable()?;
+        let data = SecretProvider::new(self.to_string(), data);
         let figment = figment.merge(data);
         Ok(figment)
     }
@@ -207,23 +219,35 @@ impl Watch for Secret {
     }
 }
 
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.namespace {
+            Some(ref ns) => write!(f, "{ns}/{}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
 struct SecretProvider {
+    name: String,
     data: BTreeMap<String, ByteString>,
 }
 
-impl From<BTreeMap<String, ByteString>> for SecretProvider {
-    fn from(value: BTreeMap<String, ByteString>) -> Self {
-        Self { data: value }
+impl SecretProvider {
+    fn new(name: String, data: BTreeMap<String, ByteString>) -> Self {
+        Self { name, data }
     }
 }
 
 impl Provider for SecretProvider {
     fn metadata(&self) -> figment::Metadata {
-        Metadata::named("k8s secret").interpolater(move |_: &Profile, k: &[&str]| {
-            let keys: Vec<_> = k.iter().map(|k| k.to_ascii_uppercase()).collect();
+        Metadata::from("k8s secret", figment::Source::Custom(self.name.clone())).interpolater(
+            move |_: &Profile, k: &[&str]| {
+                let keys: Vec<_> = k.iter().map(|k| k.to_ascii_uppercase()).collect();
 
-            keys.join(".")
-        })
+                keys.join(".")
+            },
+        )
     }
 
     fn data(
@@ -0,0 +1,238 @@
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use figment::{
+    Figment, Metadata, Profile, Provider,
+    util::nest,
+    value::{Dict, Map, Tag},
+};
+
+use super::Source;
+use crate::error::Error;
+
+/// How a data source is combined with the data sources preceding it.
+///
+/// See the [figment documentation](https://docs.rs/figment/latest/figment/struct.Figment.html#conflict-resolution)
+/// for details on conflict resolution.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum MergeStrategy {
+    /// Override existing values, replacing arrays.
+    #[default]
+    Merge,
+
+    /// Only add values that don't exist yet.
+    Join,
+
+    /// Only add values that don't exist yet, concatenating arrays.
+    Adjoin,
+
+    /// Override existing values, concatenating arrays.
+    Admerge,
+}
+
+impl MergeStrategy {
+    fn apply<P: Provider>(self, figment: Figment, provider: P) -> Figment {
+        match self {
+            MergeStrategy::Merge => figment.merge(provider),
+            MergeStrategy::Join => figment.join(provider),
+            MergeStrategy::Adjoin => figment.adjoin(provider),
+            MergeStrategy::Admerge => figment.admerge(provider),
+        }
+    }
+}
+
+impl FromStr for MergeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(Self::Merge),
+            "join" => Ok(Self::Join),
+            "adjoin" => Ok(Self::Adjoin),
+            "admerge" => Ok(Self::Admerge),
+            other => Err(Error::CliInvalidSourceOption(format!(
+                "unknown merge strategy '{other}'"
+            ))),
+        }
+    }
+}
+
+impl Display for MergeStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeStrategy::Merge => write!(f, "merge"),
+            MergeStrategy::Join => write!(f, "join"),
+            MergeStrategy::Adjoin => write!(f, "adjoin"),
+            MergeStrategy::Admerge => write!(f, "admerge"),
+        }
+    }
+}
+
+/// A data source, along with how it is layered into the context.
+pub struct Layer {
+    pub source: Box<dyn Source + Sync + Send>,
+
+    /// The key under which the data source is mounted.
+    /// If `None`, the data source is merged into the root of the context.
+    pub mount: Option<String>,
+
+    /// How the data source is combined with the preceding data sources.
+    pub strategy: MergeStrategy,
+}
+
+impl Layer {
+    pub fn new(source: Box<dyn Source + Sync + Send>) -> Self {
+        Self {
+            source,
+            mount: None,
+            strategy: MergeStrategy::default(),
+        }
+    }
+
+    /// Mount the data source under the given (possibly dotted) key.
+    pub fn with_mount<S: AsRef<str>>(mut self, mount: Option<S>) -> Self {
+        self.mount = mount.map(|s| s.as_ref().to_owned());
+        self
+    }
+
+    /// Combine the data source with the preceding ones using the given strategy.
+    pub fn with_strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Merge this layer into the given [Figment].
+    pub async fn merge_to_figment(&self, figment: Figment) -> super::Result<Figment> {
+        if self.mount.is_none() && self.strategy == MergeStrategy::Merge {
+            return self.source.merge_to_figment(figment).await;
+        }
+
+        let layer = self.source.merge_to_figment(Figment::new()).await?;
+        let figment = match self.mount {
+            Some(ref mount) => self.strategy.apply(figment, Mount::new(mount, layer)),
+            None => self.strategy.apply(figment, layer),
+        };
+        Ok(figment)
+    }
+}
+
+impl Debug for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.source)?;
+        if let Some(ref mount) = self.mount {
+            write!(f, "@{mount}")?;
+        }
+        if self.strategy != MergeStrategy::Merge {
+            write!(f, " ({})", self.strategy)?;
+        }
+        Ok(())
+    }
+}
+
+/// A provider nesting all values of a [Figment] under a key.
+///
+/// The metadata of the nested figment is retained, so the values keep
+/// pointing to the data source that originally provided them.
+struct Mount {
+    key: String,
+    figment: Figment,
+}
+
+impl Mount {
+    fn new<S: AsRef<str>>(key: S, figment: Figment) -> Self {
+        let key = key.as_ref().to_owned();
+        Self { key, figment }
+    }
+}
+
+impl Provider for Mount {
+    fn metadata(&self) -> Metadata {
+        Metadata::named(format!("mount {}", self.key))
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, figment::Error> {
+        let data = self
+            .figment
+            .data()?
+            .into_iter()
+            .map(|(profile, dict)| {
+                let nested = nest(&self.key, dict.into())
+                    .into_dict()
+                    .expect("key is non-empty: must have dict");
+                (profile, nested)
+            })
+            .collect();
+        Ok(data)
+    }
+
+    fn __metadata_map(&self) -> Option<Map<Tag, Metadata>> {
+        self.figment.__metadata_map()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_nests_values() {
+        let inner = Figment::new().merge(("cert", "abc")).merge(("key", "def"));
+        let figment = Figment::new()
+            .merge(("cert", "root"))
+            .merge(Mount::new("tls", inner));
+
+        assert_eq!(figment.extract_inner::<String>("cert").unwrap(), "root");
+        assert_eq!(figment.extract_inner::<String>("tls.cert").unwrap(), "abc");
+        assert_eq!(figment.extract_inner::<String>("tls.key").unwrap(), "def");
+    }
+
+    #[test]
+    fn test_merge_strategies() {
+        let base = || Figment::new().merge(("a", 1)).merge(("list", vec![1]));
+        let layer = || Figment::new().merge(("a", 2)).merge(("list", vec![2]));
+
+        let merged = MergeStrategy::Merge.apply(base(), layer());
+        assert_eq!(merged.extract_inner::<i32>("a").unwrap(), 2);
+        assert_eq!(merged.extract_inner::<Vec<i32>>("list").unwrap(), vec![2]);
+
+        let joined = MergeStrategy::Join.apply(base(), layer());
+        assert_eq!(joined.extract_inner::<i32>("a").unwrap(), 1);
+        assert_eq!(joined.extract_inner::<Vec<i32>>("list").unwrap(), vec![1]);
+
+        let adjoined = MergeStrategy::Adjoin.apply(base(), layer());
+        assert_eq!(adjoined.extract_inner::<i32>("a").unwrap(), 1);
+        assert_eq!(
+            adjoined.extract_inner::<Vec<i32>>("list").unwrap(),
+            vec![1, 2]
+        );
+
+        let admerged = MergeStrategy::Admerge.apply(base(), layer());
+        assert_eq!(admerged.extract_inner::<i32>("a").unwrap(), 2);
+        assert_eq!(
+            admerged.extract_inner::<Vec<i32>>("list").unwrap(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_mount_dotted_key() {
+        let inner = Figment::new().merge(("host", "db.local"));
+        let figment = Figment::new().merge(Mount::new("services.db", inner));
+
+        assert_eq!(
+            figment.extract_inner::<String>("services.db.host").unwrap(),
+            "db.local"
+        );
+    }
+
+    #[test]
+    fn test_mount_retains_metadata() {
+        let inner = Figment::new().merge(("host", "x"));
+        let expected = inner.find_metadata("host").unwrap().name.clone();
+        let figment = Figment::new().merge(Mount::new("db", inner));
+
+        let metadata = figment.find_metadata("db.host").unwrap();
+        assert_eq!(metadata.name, expected);
+        assert_ne!(metadata.name, "mount db");
+    }
+}
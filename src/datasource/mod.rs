@@ -10,6 +10,37 @@ pub use file::File;
 mod env;
 pub use env::Environment;
 
+#[cfg(feature = "file")]
+mod envfile;
+#[cfg(feature = "file")]
+pub use envfile::EnvFile;
+
+mod layer;
+pub use layer::{Layer, MergeStrategy};
+
+mod stdin;
+pub use stdin::Stdin;
+
+mod document;
+pub use document::DocumentFormat;
+#[cfg(feature = "file")]
+mod formats;
+
+#[cfg(feature = "exec")]
+mod exec;
+#[cfg(feature = "exec")]
+pub use exec::Exec;
+
+#[cfg(feature = "sops")]
+mod sops;
+#[cfg(feature = "sops")]
+pub use sops::Sops;
+
+#[cfg(feature = "url")]
+mod url;
+#[cfg(feature = "url")]
+pub use url::Url;
+
 #[cfg(feature = "k8s")]
 pub mod k8s;
 #[cfg(feature = "k8s")]
@@ -19,11 +50,11 @@ use tokio::sync::mpsc;
 
 #[derive(Clone)]
 pub struct Notifier {
-    tx: mpsc::Sender<()>,
+    tx: mpsc::Sender<String>,
 }
 
 impl Notifier {
-    pub fn new(tx: mpsc::Sender<()>) -> Self {
+    pub fn new(tx: mpsc::Sender<String>) -> Self {
         Self { tx }
     }
 
@@ -32,7 +63,7 @@ impl Notifier {
     where
         S: Display,
     {
-        if let Err(e) = self.tx.blocking_send(()) {
+        if let Err(e) = self.tx.blocking_send(source.to_string()) {
             log::warn!("Error sending notify event: {e}");
         }
         log::info!("Reload triggered by {source}");
@@ -42,7 +73,7 @@ impl Notifier {
     where
         S: Display,
     {
-        if let Err(e) = self.tx.send(()).await {
+        if let Err(e) = self.tx.send(source.to_string()).await {
             log::warn!("Error sending notify event: {e}");
         }
 
@@ -50,6 +81,56 @@ impl Notifier {
     }
 }
 
+/// Whether the values described by the metadata are secrets which must not be disclosed.
+pub fn is_sensitive(metadata: &figment::Metadata) -> bool {
+    #[cfg(feature = "sops")]
+    if metadata.name == Sops::PROVIDER {
+        return true;
+    }
+    let _ = metadata;
+    false
+}
+
+/// Watch a file, notifying when it is created, modified or removed.
+///
+/// The returned watcher must be kept alive for as long as the file is watched.
+#[cfg(any(feature = "file", feature = "sops"))]
+pub(crate) fn watch_file(
+    path: &std::path::Path,
+    notify: Notifier,
+    name: String,
+) -> Option<notify::RecommendedWatcher> {
+    use notify::{Config, EventKind, RecommendedWatcher, Watcher};
+
+    let Ok(mut watcher) = RecommendedWatcher::new(
+        move |evt: std::result::Result<notify::Event, notify::Error>| match evt {
+            Ok(e) => {
+                if matches!(
+                    e.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    notify.notify(&name);
+                }
+            }
+            Err(e) => {
+                log::warn!("Notify error: {e}")
+            }
+        },
+        Config::default(),
+    ) else {
+        log::error!("Could not create notifier for {path:?}");
+        return None;
+    };
+
+    if let Err(e) = watcher.watch(path, notify::RecursiveMode::NonRecursive) {
+        log::error!("Could not register notifier for {path:?}: {e}")
+    }
+
+    Some(watcher)
+}
+
+#[cfg(feature = "schema")]
+use crate::schema::Schema;
 use crate::{error::Error, watch::Watch};
 
 pub enum DataSourceError {
@@ -161,16 +242,36 @@ where
 #[async_trait]
 pub trait Source: std::fmt::Debug + Watch + Send {
     async fn merge_to_figment(&self, figment: Figment) -> Result<Figment>;
+
+    /// Whether the data source reads standard input, which can only be consumed once.
+    fn reads_stdin(&self) -> bool {
+        false
+    }
 }
 
 pub struct SourceRegistry {
-    pub sources: Vec<Box<dyn Source + Sync + Send>>,
+    pub sources: Vec<Layer>,
+
+    /// The schema the context is validated against.
+    #[cfg(feature = "schema")]
+    schema: Option<Schema>,
 }
 
 impl SourceRegistry {
-    pub fn new<I: Iterator<Item = Box<dyn Source + Sync + Send>>>(sources: I) -> Self {
+    pub fn new<I: Iterator<Item = Layer>>(sources: I) -> Self {
         let sources = sources.collect();
-        Self { sources }
+        Self {
+            sources,
+            #[cfg(feature = "schema")]
+            schema: None,
+        }
+    }
+
+    /// Validate the context against the given schema.
+    #[cfg(feature = "schema")]
+    pub fn with_schema(mut self, schema: Option<Schema>) -> Self {
+        self.schema = schema;
+        self
     }
 
     /// Extract the layered data sources into a [Figment].
@@ -188,12 +289,28 @@ impl SourceRegistry {
         }
         Ok(figment)
     }
+
+    /// Extract the context from the layered data sources.
+    ///
+    /// If a schema is set, the context is validated against it.
+    pub async fn context(&self) -> crate::error::Result<serde_json::Value> {
+        let ctx = self.as_figment().await?.extract()?;
+
+        #[cfg(feature = "schema")]
+        if let Some(ref schema) = self.schema {
+            schema.validate(&ctx)?;
+        }
+
+        Ok(ctx)
+    }
 }
 
 impl Debug for SourceRegistry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("SourceRegistry")
-            .field("sources", &self.sources)
-            .finish()
+        let mut f = f.debug_struct("SourceRegistry");
+        f.field("sources", &self.sources);
+        #[cfg(feature = "schema")]
+        f.field("schema", &self.schema.is_some());
+        f.finish()
     }
 }
@@ -0,0 +1,531 @@
+use std::path::{Path, PathBuf};
+
+use aes_gcm::{
+    AesGcm, KeyInit,
+    aead::{Aead, Payload, consts::U32},
+    aes::Aes256,
+};
+use async_trait::async_trait;
+use base64::Engine as _;
+use figment::Figment;
+use hex::ToHex;
+use notify::RecommendedWatcher;
+use regex::Regex;
+use serde_yaml::Value;
+use sha2::{Digest, Sha512};
+use subtle::ConstantTimeEq;
+
+use super::{DocumentFormat, Notifier, Source, ToDataSourceError, watch_file};
+use crate::{error::Error, watch::Watch};
+
+/// AES-256-GCM with the 32 byte nonces used by SOPS.
+type Cipher = AesGcm<Aes256, U32>;
+
+/// A SOPS document encrypted with age, decrypted in-process.
+///
+/// The age identities are taken from the given key file, or like `sops` does,
+/// from the `SOPS_AGE_KEY` or `SOPS_AGE_KEY_FILE` environment variables,
+/// falling back to `$XDG_CONFIG_HOME/sops/age/keys.txt`.
+pub struct Sops {
+    path: PathBuf,
+    key_file: Option<PathBuf>,
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl Sops {
+    /// The name of the provider of decrypted values.
+    pub const PROVIDER: &str = "sops";
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_owned();
+        Self {
+            path,
+            key_file: None,
+            watcher: None,
+        }
+    }
+
+    /// Read the age identities from the given file.
+    pub fn with_key_file<P: AsRef<Path>>(mut self, key_file: Option<P>) -> Self {
+        self.key_file = key_file.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    fn identities(&self) -> Result<Vec<Box<dyn age::Identity>>, Error> {
+        let identity_file = match self.key_file {
+            Some(ref path) => age::IdentityFile::from_file(path.to_string_lossy().into_owned())?,
+            None => match std::env::var("SOPS_AGE_KEY") {
+                Ok(key) => age::IdentityFile::from_buffer(key.as_bytes())?,
+                Err(_) => {
+                    let path = std::env::var_os("SOPS_AGE_KEY_FILE")
+                        .map(PathBuf::from)
+                        .or_else(|| {
+                            std::env::var_os("XDG_CONFIG_HOME")
+                                .map(PathBuf::from)
+                                .or_else(|| {
+                                    std::env::var_os("HOME")
+                                        .map(|home| PathBuf::from(home).join(".config"))
+                                })
+                                .map(|config| config.join("sops/age/keys.txt"))
+                        })
+                        .ok_or_else(|| Error::SopsError("no age key given".into()))?;
+                    age::IdentityFile::from_file(path.to_string_lossy().into_owned())?
+                }
+            },
+        };
+
+        identity_file
+            .into_identities()
+            .map_err(|e| Error::SopsError(format!("invalid age key: {e}")))
+    }
+
+    /// Read and decrypt the document.
+    fn decrypt(&self) -> Result<Value, Error> {
+        let format = self
+            .path
+            .extension()
+            .and_then(|ext| DocumentFormat::from_extension(&ext.to_string_lossy()))
+            .filter(|format| matches!(format, DocumentFormat::Json | DocumentFormat::Yaml))
+            .ok_or_else(|| Error::UnknownFileType {
+                path: self.path.clone(),
+            })?;
+        log::trace!("Reading {format} SOPS document {:?}", self.path);
+
+        // JSON is parsed as YAML, which retains the order of keys the MAC depends on.
+        let Value::Mapping(mut document) =
+            serde_yaml::from_reader(std::fs::File::open(&self.path)?)?
+        else {
+            return Err(Error::SopsError("document is not a mapping".into()));
+        };
+        // removed without moving other keys, as the MAC depends on their order.
+        let metadata = document
+            .shift_remove("sops")
+            .ok_or_else(|| Error::SopsError("document is not encrypted with sops".into()))?;
+
+        let rules = Rules::from_metadata(&metadata)?;
+        let key = data_key(&metadata, &self.identities()?)?;
+        let mac_only_encrypted = metadata
+            .get("mac_only_encrypted")
+            .and_then(Value::as_bool)
+            .unwrap_or_default();
+
+        let mut decryptor = Decryptor {
+            cipher: Cipher::new_from_slice(&key)
+                .map_err(|_| Error::SopsError("invalid data key".into()))?,
+            mac: Sha512::new(),
+            rules,
+            mac_only_encrypted,
+        };
+        let mut document = Value::Mapping(document);
+        decryptor.walk(&mut document, &mut vec![])?;
+
+        let last_modified = metadata
+            .get("lastmodified")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::SopsError("lastmodified is missing".into()))?;
+        let mac = metadata
+            .get("mac")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::SopsError("mac is missing".into()))?;
+        let (expected, _) = decryptor.decrypt(mac, last_modified)?;
+        let actual: String = decryptor.mac.finalize().encode_hex_upper();
+        if !bool::from(expected.ct_eq(actual.as_bytes())) {
+            return Err(Error::SopsError(
+                "MAC mismatch, the document was tampered with".into(),
+            ));
+        }
+
+        Ok(document)
+    }
+}
+
+/// Decrypt the data key using any of the identities.
+fn data_key(metadata: &Value, identities: &[Box<dyn age::Identity>]) -> Result<Vec<u8>, Error> {
+    let recipients = metadata
+        .get("age")
+        .and_then(Value::as_sequence)
+        .ok_or_else(|| Error::SopsError("document is not encrypted with age".into()))?;
+
+    for enc in recipients
+        .iter()
+        .filter_map(|recipient| recipient.get("enc")?.as_str())
+    {
+        let armored = age::armor::ArmoredReader::new(enc.as_bytes());
+        let Ok(decryptor) = age::Decryptor::new_buffered(armored) else {
+            continue;
+        };
+        if let Ok(mut reader) = decryptor.decrypt(identities.iter().map(|i| i.as_ref() as _)) {
+            let mut key = vec![];
+            std::io::Read::read_to_end(&mut reader, &mut key)?;
+            return Ok(key);
+        }
+    }
+
+    Err(Error::SopsError(
+        "none of the age keys can decrypt the document".into(),
+    ))
+}
+
+/// Which values are encrypted, according to the paths of their keys.
+///
+/// These are the rules `sops` applies, with the suffixes and regular expressions
+/// taken from the metadata of the document.
+#[derive(Debug, Default)]
+struct Rules {
+    unencrypted_suffix: Option<String>,
+    encrypted_suffix: Option<String>,
+    unencrypted_regex: Option<Regex>,
+    encrypted_regex: Option<Regex>,
+}
+
+impl Rules {
+    /// The suffix of unencrypted keys if the metadata sets no rules.
+    const DEFAULT_UNENCRYPTED_SUFFIX: &str = "_unencrypted";
+
+    fn from_metadata(metadata: &Value) -> Result<Self, Error> {
+        let field = |name: &str| {
+            metadata
+                .get(name)
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+        };
+        let regex = |name: &str| {
+            field(name)
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .map_err(|e| Error::SopsError(format!("invalid {name}: {e}")))
+                })
+                .transpose()
+        };
+
+        // these select values by the comments above them, which are not retained when parsing.
+        for name in ["unencrypted_comment_regex", "encrypted_comment_regex"] {
+            if field(name).is_some() {
+                return Err(Error::SopsError(format!("{name} is not supported")));
+            }
+        }
+
+        let rules = Self {
+            unencrypted_suffix: field("unencrypted_suffix").map(ToOwned::to_owned),
+            encrypted_suffix: field("encrypted_suffix").map(ToOwned::to_owned),
+            unencrypted_regex: regex("unencrypted_regex")?,
+            encrypted_regex: regex("encrypted_regex")?,
+        };
+
+        match rules {
+            Self {
+                unencrypted_suffix: None,
+                encrypted_suffix: None,
+                unencrypted_regex: None,
+                encrypted_regex: None,
+            } => Ok(Self {
+                unencrypted_suffix: Some(Self::DEFAULT_UNENCRYPTED_SUFFIX.into()),
+                ..rules
+            }),
+            rules => Ok(rules),
+        }
+    }
+
+    /// Is the value at the given path encrypted.
+    fn encrypted(&self, path: &[String]) -> bool {
+        let mut encrypted = true;
+        if let Some(ref suffix) = self.unencrypted_suffix
+            && path.iter().any(|key| key.ends_with(suffix.as_str()))
+        {
+            encrypted = false;
+        }
+        if let Some(ref suffix) = self.encrypted_suffix {
+            encrypted = path.iter().any(|key| key.ends_with(suffix.as_str()));
+        }
+        if let Some(ref regex) = self.unencrypted_regex
+            && path.iter().any(|key| regex.is_match(key))
+        {
+            encrypted = false;
+        }
+        if let Some(ref regex) = self.encrypted_regex {
+            encrypted = path.iter().any(|key| regex.is_match(key));
+        }
+        encrypted
+    }
+}
+
+struct Decryptor {
+    cipher: Cipher,
+    mac: Sha512,
+    rules: Rules,
+    mac_only_encrypted: bool,
+}
+
+impl Decryptor {
+    /// Decrypt all values in place, computing the MAC along the way.
+    ///
+    /// Items of sequences share the path of their sequence.
+    fn walk(&mut self, value: &mut Value, path: &mut Vec<String>) -> Result<(), Error> {
+        match value {
+            Value::Mapping(mapping) => {
+                for (k, v) in mapping.iter_mut() {
+                    path.push(key_to_string(k));
+                    self.walk(v, path)?;
+                    path.pop();
+                }
+            }
+            Value::Sequence(sequence) => {
+                for v in sequence {
+                    self.walk(v, path)?;
+                }
+            }
+            Value::Tagged(tagged) => self.walk(&mut tagged.value, path)?,
+            leaf if self.rules.encrypted(path) => {
+                match leaf {
+                    // empty values are not encrypted by `sops`.
+                    Value::Null => {}
+                    Value::String(s) if s.is_empty() => {}
+                    Value::String(s) => {
+                        let aad = path.iter().map(|p| format!("{p}:")).collect::<String>();
+                        let (plaintext, kind) = self.decrypt(s, &aad)?;
+                        *leaf = typed_value(plaintext, &kind)?;
+                    }
+                    _ => {
+                        return Err(Error::SopsError(format!(
+                            "value at '{}' is not encrypted",
+                            path.join(":")
+                        )));
+                    }
+                }
+                // the MAC covers the typed value, e.g. `True` for a boolean decrypted from `true`.
+                self.mac.update(leaf_to_bytes(leaf));
+            }
+            leaf => {
+                if !self.mac_only_encrypted {
+                    self.mac.update(leaf_to_bytes(leaf));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypt a value of the form `ENC[AES256_GCM,data:...,iv:...,tag:...,type:...]`.
+    ///
+    /// Returns the plaintext and its type.
+    fn decrypt(&self, value: &str, aad: &str) -> Result<(Vec<u8>, String), Error> {
+        let invalid = || Error::SopsError(format!("invalid encrypted value at '{aad}'"));
+
+        let fields = value
+            .strip_prefix("ENC[AES256_GCM,")
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or_else(invalid)?;
+        let field = |name: &str| {
+            fields
+                .split(',')
+                .find_map(|f| f.strip_prefix(name)?.strip_prefix(':'))
+                .ok_or_else(invalid)
+        };
+        let b64 = |name: &str| {
+            base64::engine::general_purpose::STANDARD
+                .decode(field(name)?)
+                .map_err(|_| invalid())
+        };
+
+        let iv = b64("iv")?;
+        if iv.len() != 32 {
+            return Err(invalid());
+        }
+        let mut ciphertext = b64("data")?;
+        ciphertext.extend(b64("tag")?);
+
+        let plaintext = self
+            .cipher
+            .decrypt(
+                iv.as_slice().into(),
+                Payload {
+                    msg: &ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::SopsError(format!("could not decrypt value at '{aad}'")))?;
+
+        Ok((plaintext, field("type")?.to_owned()))
+    }
+}
+
+fn key_to_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim_end()
+            .to_owned(),
+    }
+}
+
+/// Convert a decrypted plaintext to a value of the given type.
+fn typed_value(plaintext: Vec<u8>, kind: &str) -> Result<Value, Error> {
+    let text = String::from_utf8_lossy(&plaintext);
+    let invalid = || Error::SopsError(format!("invalid {kind} value"));
+
+    let value = match kind {
+        "str" | "bytes" => Value::String(text.into_owned()),
+        "int" => Value::Number(text.parse::<i64>().map_err(|_| invalid())?.into()),
+        "float" => Value::Number(text.parse::<f64>().map_err(|_| invalid())?.into()),
+        "bool" => Value::Bool(text.eq_ignore_ascii_case("true")),
+        _ => return Err(invalid()),
+    };
+    Ok(value)
+}
+
+/// The bytes an unencrypted value contributes to the MAC, as formatted by `sops`.
+fn leaf_to_bytes(value: &Value) -> Vec<u8> {
+    match value {
+        Value::String(s) => s.as_bytes().to_vec(),
+        // Floats are formatted without a trailing `.0`, like Go's `strconv.FormatFloat`.
+        Value::Number(n) if n.is_f64() => n.as_f64().unwrap_or_default().to_string().into_bytes(),
+        Value::Number(n) => n.to_string().into_bytes(),
+        Value::Bool(true) => b"True".to_vec(),
+        Value::Bool(false) => b"False".to_vec(),
+        _ => vec![],
+    }
+}
+
+#[async_trait]
+impl Source for Sops {
+    async fn merge_to_figment(&self, figment: Figment) -> super::Result<Figment> {
+        let document = self.decrypt().fatal()?;
+        let json = serde_json::to_string(&document).fatal()?;
+
+        Ok(
+            DocumentFormat::Json.merge(
+                figment,
+                Self::PROVIDER,
+                &self.path.to_string_lossy(),
+                &json,
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Watch for Sops {
+    async fn watch(&mut self, notify: Notifier) {
+        self.watcher = watch_file(&self.path, notify, format!("{:?}", *self));
+    }
+}
+
+impl std::fmt::Debug for Sops {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sops").field("path", &self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    /// Documents in the format written by `sops`, encrypted for the age key in `keys.txt`.
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/datasource/testdata/sops")
+            .join(name)
+    }
+
+    fn decrypt(path: &Path) -> Result<serde_json::Value, Error> {
+        let sops = Sops::new(path).with_key_file(Some(fixture("keys.txt")));
+        Ok(serde_json::to_value(sops.decrypt()?).unwrap())
+    }
+
+    #[test]
+    fn test_decrypt_yaml() {
+        assert_eq!(
+            decrypt(&fixture("secrets.yaml")).unwrap(),
+            serde_json::json!({
+                "db": {
+                    "host": "db.internal",
+                    "port": 5432,
+                    "password": "s3cret",
+                    "ratio": 0.75,
+                    "tls": true,
+                },
+                "hosts": ["a.example.com", "b.example.com"],
+                "replicas": 3,
+                "weights": [1.5, 2, false],
+                "empty": "",
+                "public_unencrypted": { "note": "plain text", "count": 7 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_decrypt_json() {
+        // only values matching the encrypted_regex of the document are encrypted.
+        assert_eq!(
+            decrypt(&fixture("secrets.json")).unwrap(),
+            serde_json::json!({
+                "db": { "host": "db.internal", "port": 5432, "password": "s3cret" },
+                "api_token": "t0k3n",
+                "ratio": 0.25,
+                "enabled": false,
+                "hosts": ["a.example.com", "b.example.com"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = std::fs::read_to_string(fixture("secrets.yaml")).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+
+        // the metadata may be anywhere in the document.
+        let (values, metadata) = document.split_once("sops:\n").unwrap();
+        let reordered = write("reordered.yaml", &format!("sops:\n{metadata}{values}"));
+        assert!(decrypt(&reordered).is_ok());
+
+        let tampered = write(
+            "tampered.yaml",
+            &document.replace("plain text", "tampered text"),
+        );
+        assert!(decrypt(&tampered).is_err_and(|e| e.to_string().contains("MAC mismatch")));
+
+        let unencrypted = write(
+            "unencrypted.yaml",
+            &document.replace(
+                "unencrypted_suffix: _unencrypted",
+                "encrypted_suffix: _secret",
+            ),
+        );
+        assert!(decrypt(&unencrypted).is_err());
+
+        let other = age::x25519::Identity::generate();
+        let other_key = write("other.txt", other.to_string().expose_secret());
+        let wrong_key = Sops::new(fixture("secrets.yaml")).with_key_file(Some(&other_key));
+        assert!(wrong_key.decrypt().is_err());
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = |metadata: &str| {
+            let metadata = serde_yaml::from_str(metadata).unwrap();
+            Rules::from_metadata(&metadata).unwrap()
+        };
+        let path = |path: &[&str]| path.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+
+        let default = rules("version: 3.9.4");
+        assert!(default.encrypted(&path(&["db", "password"])));
+        assert!(!default.encrypted(&path(&["public_unencrypted", "note"])));
+
+        let suffix = rules("encrypted_suffix: _secret");
+        assert!(suffix.encrypted(&path(&["db_secret", "password"])));
+        assert!(!suffix.encrypted(&path(&["db", "password"])));
+
+        let regex = rules("unencrypted_regex: ^(host|port)$");
+        assert!(!regex.encrypted(&path(&["db", "host"])));
+        assert!(regex.encrypted(&path(&["db", "password"])));
+
+        let metadata = serde_yaml::from_str("encrypted_comment_regex: sops:enc").unwrap();
+        assert!(Rules::from_metadata(&metadata).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use figment::Figment;
+use tokio::io::AsyncReadExt;
+use tokio::sync::OnceCell;
+
+use super::{DocumentFormat, Source, ToDataSourceError};
+use crate::watch::Watch;
+
+/// A document read from standard input.
+///
+/// Standard input is read once, on the first render. The document is reused on reloads.
+pub struct Stdin {
+    format: DocumentFormat,
+    document: OnceCell<String>,
+}
+
+impl Stdin {
+    pub fn new(format: DocumentFormat) -> Self {
+        Self {
+            format,
+            document: OnceCell::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Source for Stdin {
+    async fn merge_to_figment(&self, figment: Figment) -> super::Result<Figment> {
+        let document = self
+            .document
+            .get_or_try_init(|| async {
+                log::info!("Reading {} data source from standard input", self.format);
+                let mut document = String::new();
+                tokio::io::stdin().read_to_string(&mut document).await?;
+                Ok::<_, std::io::Error>(document)
+            })
+            .await
+            .fatal()?;
+
+        Ok(self.format.merge(figment, "stdin", "-", document))
+    }
+
+    fn reads_stdin(&self) -> bool {
+        true
+    }
+}
+
+impl Watch for Stdin {}
+
+impl std::fmt::Debug for Stdin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stdin")
+            .field("format", &self.format)
+            .finish()
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use figment::Figment;
+use mime::Mime;
+use reqwest::{StatusCode, header};
+
+use super::{DocumentFormat, Notifier, Source, ToDataSourceError};
+use crate::{error::Error, watch::Watch};
+
+/// Determine the format from the Content-Type, falling back to the extension of the URL path.
+fn detect_format(content_type: Option<&str>, url: &reqwest::Url) -> Option<DocumentFormat> {
+    content_type
+        .and_then(|s| s.parse::<Mime>().ok())
+        .and_then(|mime| DocumentFormat::from_mime(&mime))
+        .or_else(|| DocumentFormat::from_extension(url.path().rsplit_once('.')?.1))
+}
+
+/// The last document fetched from a URL.
+#[derive(Debug, PartialEq)]
+struct Fetched {
+    etag: Option<String>,
+    content_type: Option<String>,
+    body: String,
+}
+
+/// A JSON, YAML or TOML document fetched over HTTP.
+pub struct Url {
+    url: reqwest::Url,
+    interval: Duration,
+    client: reqwest::Client,
+
+    /// The `Host` header sent, if it differs from the host of the URL.
+    host: Option<String>,
+
+    /// Shared with the watcher, so both can make conditional requests.
+    last: Arc<Mutex<Option<Arc<Fetched>>>>,
+}
+
+impl Url {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+    pub fn new(url: reqwest::Url, interval: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .read_timeout(Duration::from_secs(30))
+            .build()
+            .expect("default client must build");
+        Self {
+            url,
+            interval,
+            client,
+            host: None,
+            last: Default::default(),
+        }
+    }
+
+    /// Use the given client, e.g. one with custom TLS settings, rather than a default one.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Send the given `Host` header, e.g. if the URL names another server than the one connected to.
+    pub fn with_host(mut self, host: Option<String>) -> Self {
+        self.host = host;
+        self
+    }
+}
+
+/// Fetch the document, using `If-None-Match` if a previous response had an ETag.
+///
+/// Returns the current document, and whether it differs from the previously fetched one.
+async fn fetch(
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    host: Option<&str>,
+    last: &Mutex<Option<Arc<Fetched>>>,
+) -> Result<(Arc<Fetched>, bool), Error> {
+    let previous = last.lock().expect("lock poisoned").clone();
+
+    let mut request = client.get(url.clone());
+    if let Some(host) = host {
+        request = request.header(header::HOST, host);
+    }
+    if let Some(etag) = previous.as_ref().and_then(|p| p.etag.as_ref()) {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED
+        && let Some(previous) = previous
+    {
+        log::debug!("{url} not modified");
+        return Ok((previous, false));
+    }
+    if !status.is_success() {
+        return Err(Error::HttpStatus {
+            url: url.to_string(),
+            status: status.as_u16(),
+        });
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned)
+    };
+    let etag = header(header::ETAG);
+    let content_type = header(header::CONTENT_TYPE);
+    let body = response.text().await?;
+
+    let fetched = Arc::new(Fetched {
+        etag,
+        content_type,
+        body,
+    });
+    let changed = previous.is_none_or(|p| p.body != fetched.body);
+    last.lock().expect("lock poisoned").replace(fetched.clone());
+
+    Ok((fetched, changed))
+}
+
+#[async_trait]
+impl Source for Url {
+    async fn merge_to_figment(&self, figment: Figment) -> super::Result<Figment> {
+        let (fetched, _) = fetch(&self.client, &self.url, self.host.as_deref(), &self.last)
+            .await
+            .recoverable()?;
+
+        let name = self.url.to_string();
+        let Some(format) = detect_format(fetched.content_type.as_deref(), &self.url) else {
+            return Err(Error::UnknownContentType {
+                url: name,
+                content_type: fetched.content_type.clone().unwrap_or_default(),
+            })
+            .fatal();
+        };
+
+        let figment = format.merge(figment, "url", &name, &fetched.body);
+        Ok(figment)
+    }
+}
+
+#[async_trait]
+impl Watch for Url {
+    async fn watch(&mut self, notify: Notifier) {
+        let self_dbg = format!("{:?}", *self);
+        let client = self.client.clone();
+        let url = self.url.clone();
+        let host = self.host.clone();
+        let last = self.last.clone();
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        tokio::spawn(async move {
+            // The first tick completes immediately, while the document was just fetched.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match fetch(&client, &url, host.as_deref(), &last).await {
+                    Ok((_, true)) => notify.notify_async(&self_dbg).await,
+                    Ok((_, false)) => {}
+                    Err(e) => log::warn!("Could not poll {url}: {e}"),
+                }
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Url")
+            .field("url", &self.url.as_str())
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        let url = |s: &str| s.parse::<reqwest::Url>().unwrap();
+
+        let detect = |content_type, u| detect_format(content_type, &url(u));
+        assert_eq!(
+            detect(Some("application/json; charset=utf-8"), "http://a/"),
+            Some(DocumentFormat::Json)
+        );
+        assert_eq!(
+            detect(Some("application/vnd.api+json"), "http://a/"),
+            Some(DocumentFormat::Json)
+        );
+        assert_eq!(
+            detect(Some("application/x-yaml"), "http://a/"),
+            Some(DocumentFormat::Yaml)
+        );
+        assert_eq!(
+            detect(Some("application/toml"), "http://a/"),
+            Some(DocumentFormat::Toml)
+        );
+        assert_eq!(
+            detect(Some("text/plain"), "http://a/c.yml"),
+            Some(DocumentFormat::Yaml)
+        );
+        assert_eq!(detect(None, "http://a/config"), None);
+    }
+}
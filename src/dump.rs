@@ -0,0 +1,108 @@
+use figment::{Figment, Metadata, Source, value::Value};
+use serde_json::json;
+
+use crate::datasource::is_sensitive;
+use crate::error::Result;
+
+/// The format to dump the context in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DumpFormat {
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+/// Describe the data source a value was taken from.
+fn describe(metadata: &Metadata) -> String {
+    match metadata.source {
+        Some(Source::Code(_)) | None => metadata.name.to_string(),
+        Some(ref source) => format!("{} ({source})", metadata.name),
+    }
+}
+
+/// The placeholder for values taken from data sources holding secrets.
+const REDACTED: &str = "<redacted>";
+
+/// Convert a value to JSON, redacting sensitive values.
+///
+/// If `explain` is true, each leaf is replaced with its value and the data source it was taken from.
+fn to_json(figment: &Figment, value: Value, explain: bool) -> serde_json::Value {
+    match value {
+        Value::Dict(_, dict) => dict
+            .into_iter()
+            .map(|(k, v)| (k, to_json(figment, v, explain)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::Array(_, array) => array
+            .into_iter()
+            .map(|v| to_json(figment, v, explain))
+            .collect::<Vec<_>>()
+            .into(),
+        leaf => {
+            let metadata = figment.get_metadata(leaf.tag());
+            let value = match metadata {
+                Some(metadata) if is_sensitive(metadata) => json!(REDACTED),
+                _ => serde_json::to_value(&leaf).unwrap_or_default(),
+            };
+            if explain {
+                json!({ "value": value, "source": metadata.map(describe) })
+            } else {
+                value
+            }
+        }
+    }
+}
+
+/// Render the merged context in the given format.
+///
+/// If `explain` is true, each leaf is annotated with the data source that provided it.
+/// Values from data sources holding secrets are redacted.
+pub fn dump_context(figment: &Figment, format: DumpFormat, explain: bool) -> Result<String> {
+    let value = to_json(figment, figment.find_value("")?, explain);
+
+    let dumped = match format {
+        DumpFormat::Json => serde_json::to_string_pretty(&value)? + "\n",
+        #[cfg(feature = "yaml")]
+        DumpFormat::Yaml => serde_yaml::to_string(&value)?,
+    };
+
+    Ok(dumped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::providers::{Format, Json, Toml};
+
+    #[test]
+    fn test_explain() {
+        let figment = Figment::new()
+            .merge(Json::string(r#"{"a": 1, "b": {"c": [true]}}"#))
+            .merge(Toml::string("a = 2"));
+
+        let explained = to_json(&figment, figment.find_value("").unwrap(), true);
+        assert_eq!(
+            explained,
+            json!({
+                "a": { "value": 2, "source": "TOML source string" },
+                "b": { "c": [{ "value": true, "source": "JSON source string" }] },
+            })
+        );
+    }
+
+    #[cfg(feature = "sops")]
+    #[test]
+    fn test_redact_sensitive() {
+        use crate::datasource::{DocumentFormat, Sops};
+
+        let figment = Figment::new().merge(Json::string(r#"{"user": "app"}"#));
+        let figment =
+            DocumentFormat::Json.merge(figment, Sops::PROVIDER, "s.json", r#"{"pass": "x"}"#);
+
+        let dumped = dump_context(&figment, DumpFormat::Json, false).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&dumped).unwrap(),
+            json!({ "user": "app", "pass": REDACTED })
+        );
+    }
+}
@@ -23,6 +23,33 @@ pub enum Error {
     #[error("Argument error: {0}")]
     ClapError(#[from] clap::error::Error),
 
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[cfg(feature = "yaml")]
+    #[error("YAML error: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+
+    #[cfg(feature = "schema")]
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(String),
+
+    #[cfg(feature = "schema")]
+    #[error("Context does not match the schema:\n{}", .0.join("\n"))]
+    SchemaViolation(Vec<String>),
+
+    #[error("Invalid env file {0:?}: {1}")]
+    InvalidEnvFile(PathBuf, String),
+
+    #[error("Invalid front matter in {0:?}: {1}")]
+    InvalidFrontMatter(PathBuf, String),
+
+    #[error("Template {template:?} requires keys missing from the context: {}", .keys.join(", "))]
+    MissingRequiredKeys {
+        template: PathBuf,
+        keys: Vec<String>,
+    },
+
     #[error("Templating error: {0:#}")]
     TemplateError(#[from] minijinja::Error),
 
@@ -32,6 +59,46 @@ pub enum Error {
     #[error("Invalid signal argument")]
     CliInvalidSignal,
 
+    #[error("Invalid signal target: {0}")]
+    CliInvalidSignalTarget(String),
+
+    #[error("Invalid data source option: {0}")]
+    CliInvalidSourceOption(String),
+
+    #[cfg(feature = "http")]
+    #[error("Invalid HTTP client profile: {0}")]
+    CliInvalidHttpProfile(String),
+
+    #[cfg(feature = "reload-http")]
+    #[error("Invalid on-reload HTTP option: {0}")]
+    CliInvalidReloadHttp(String),
+
+    #[error("Command {command:?} failed with {status}{}", match .stderr.as_str() {
+        "" => String::new(),
+        stderr => format!(": {stderr}"),
+    })]
+    CommandFailed {
+        command: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+
+    #[cfg(feature = "url")]
+    #[error("HTTP error: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
+    #[cfg(feature = "url")]
+    #[error("HTTP request to {url} failed with status {status}")]
+    HttpStatus { url: String, status: u16 },
+
+    #[cfg(feature = "url")]
+    #[error("Unknown content type of {url}: {content_type:?}")]
+    UnknownContentType { url: String, content_type: String },
+
+    #[cfg(feature = "sops")]
+    #[error("SOPS error: {0}")]
+    SopsError(String),
+
     #[cfg(feature = "k8s")]
     #[error("K8s Error: {0}")]
     KubeError(#[from] kube::Error),
@@ -152,14 +152,14 @@ mod tests {
 
     #[test]
     fn test_base64encode_byte_seq() {
-        let value = Value::from_serialize(&[1, 2, 3, 4]);
+        let value = Value::from_serialize([1, 2, 3, 4]);
         let base64 = base64encode(&value).unwrap();
         assert_eq!(base64, "AQIDBA==")
     }
 
     #[test]
     fn test_base64encode_non_byte_seq() {
-        let value = Value::from_serialize(&[257]);
+        let value = Value::from_serialize([257]);
         let error = base64encode(&value).unwrap_err();
         assert_eq!(error.kind(), minijinja::ErrorKind::InvalidOperation);
         assert_eq!(error.detail(), Some("Invalid sequence (not u8)!"));
@@ -167,7 +167,7 @@ mod tests {
 
     #[test]
     fn test_base64encode_non_numeric_seq() {
-        let value = Value::from_serialize(&["hello"]);
+        let value = Value::from_serialize(["hello"]);
         let error = base64encode(&value).unwrap_err();
         assert_eq!(error.kind(), minijinja::ErrorKind::InvalidOperation);
         assert_eq!(error.detail(), Some("Invalid sequence (not numeric)!"));
@@ -190,14 +190,14 @@ mod tests {
 
     #[test]
     fn test_hexencode_bytes() {
-        let value = Value::from_serialize(&[1, 2, 3, 4]);
+        let value = Value::from_serialize([1, 2, 3, 4]);
         let hex = hexencode(&value).unwrap();
         assert_eq!(hex, "01020304");
     }
 
     #[test]
     fn test_hexencode_non_byte_seq() {
-        let value = Value::from_serialize(&[257]);
+        let value = Value::from_serialize([257]);
         let error = hexencode(&value).unwrap_err();
         assert_eq!(error.kind(), minijinja::ErrorKind::InvalidOperation);
         assert_eq!(error.detail(), Some("Invalid sequence (not u8)!"));
@@ -205,7 +205,7 @@ mod tests {
 
     #[test]
     fn test_hexencode_non_numeric_seq() {
-        let value = Value::from_serialize(&["hello"]);
+        let value = Value::from_serialize(["hello"]);
         let error = hexencode(&value).unwrap_err();
         assert_eq!(error.kind(), minijinja::ErrorKind::InvalidOperation);
         assert_eq!(error.detail(), Some("Invalid sequence (not numeric)!"));
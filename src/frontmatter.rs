@@ -0,0 +1,306 @@
+use std::ffi::{OsStr, OsString};
+use std::sync::Arc;
+
+use minijinja::Value;
+use minijinja::value::{Enumerator, Object, ValueKind};
+use serde::{Deserialize, Deserializer};
+
+use crate::reload::{OnReloadAction, OnReloadSignalTarget, parse_signal};
+
+/// Metadata declared in a block of YAML between `---` lines at the start of a template.
+#[derive(Debug, Default, Clone, Hash, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FrontMatter {
+    /// Dotted keys that must be present in the context.
+    pub required: Vec<String>,
+
+    /// Values used for keys missing from the context.
+    pub defaults: Value,
+
+    /// The permissions of the rendered file.
+    #[serde(deserialize_with = "deserialize_mode")]
+    pub mode: Option<u32>,
+
+    /// An action executed when the rendered file changed.
+    #[serde(deserialize_with = "deserialize_on_reload")]
+    pub on_reload: Option<OnReloadAction>,
+}
+
+impl FrontMatter {
+    /// Split the front matter off a template.
+    ///
+    /// A leading block of YAML is only considered front matter if it is a
+    /// mapping containing nothing but front matter keys, so templates of e.g.
+    /// YAML documents starting with `---` are left untouched.
+    /// Returns the front matter and the remaining template.
+    #[cfg(feature = "yaml")]
+    pub fn split(template: &str) -> Result<(Self, &str), String> {
+        const KEYS: &[&str] = &["required", "defaults", "mode", "on-reload"];
+
+        let Some(rest) = template
+            .strip_prefix("---\n")
+            .or_else(|| template.strip_prefix("---\r\n"))
+        else {
+            return Ok((Self::default(), template));
+        };
+
+        let mut offset = 0;
+        let (yaml, body) = loop {
+            let Some(line) = rest[offset..].split_inclusive('\n').next() else {
+                return Ok((Self::default(), template));
+            };
+            if line.trim_end_matches(['\r', '\n']) == "---" {
+                break (&rest[..offset], &rest[offset + line.len()..]);
+            }
+            offset += line.len();
+        };
+
+        let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::from_str(yaml) else {
+            return Ok((Self::default(), template));
+        };
+        if !mapping
+            .keys()
+            .all(|k| k.as_str().is_some_and(|k| KEYS.contains(&k)))
+        {
+            return Ok((Self::default(), template));
+        }
+
+        let front_matter: Self = serde_yaml::from_value(serde_yaml::Value::Mapping(mapping))
+            .map_err(|e| e.to_string())?;
+        if !front_matter.defaults.is_undefined() && front_matter.defaults.kind() != ValueKind::Map {
+            return Err("defaults must be a mapping".into());
+        }
+
+        Ok((front_matter, body))
+    }
+
+    #[cfg(not(feature = "yaml"))]
+    pub fn split(template: &str) -> Result<(Self, &str), String> {
+        Ok((Self::default(), template))
+    }
+
+    /// Return all required keys missing from the context.
+    pub fn missing_keys(&self, ctx: &Value) -> Vec<String> {
+        self.required
+            .iter()
+            .filter(|key| {
+                key.split('.')
+                    .try_fold(ctx.clone(), |value, segment| {
+                        value.get_attr(segment).ok().filter(|v| !v.is_undefined())
+                    })
+                    .is_none()
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Wrap the context so that missing keys are taken from the defaults.
+    pub fn apply_defaults(&self, ctx: &Value) -> Value {
+        if self.defaults.is_undefined() {
+            return ctx.clone();
+        }
+
+        Value::from_object(WithDefaults {
+            ctx: ctx.clone(),
+            defaults: self.defaults.clone(),
+        })
+    }
+}
+
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let Some(mode) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid octal mode '{mode}'")))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExecSpec {
+    Path(String),
+
+    /// The executable, followed by its arguments.
+    Argv(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OnReloadSpec {
+    command: Option<String>,
+    exec: Option<ExecSpec>,
+    signal: Option<String>,
+    target: Option<String>,
+}
+
+fn deserialize_on_reload<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<OnReloadAction>, D::Error> {
+    use serde::de::Error;
+
+    let Some(spec) = Option::<OnReloadSpec>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    let action = match spec {
+        OnReloadSpec {
+            command: Some(command),
+            exec: None,
+            signal: None,
+            target: None,
+        } => OnReloadAction::ShellCommand(command.into()),
+        OnReloadSpec {
+            command: None,
+            exec: Some(exec),
+            signal: None,
+            target: None,
+        } => {
+            let (path, args) = match exec {
+                ExecSpec::Path(path) => (path, vec![]),
+                ExecSpec::Argv(mut argv) if !argv.is_empty() => {
+                    let args = argv.split_off(1);
+                    (argv.remove(0), args)
+                }
+                ExecSpec::Argv(_) => return Err(D::Error::custom("on-reload exec is empty")),
+            };
+            OnReloadAction::Executable {
+                path: path.into(),
+                args: args.into_iter().map(OsString::from).collect(),
+            }
+        }
+        OnReloadSpec {
+            command: None,
+            exec: None,
+            signal: Some(signal),
+            target,
+        } => {
+            let signal = parse_signal(&signal)
+                .ok_or_else(|| D::Error::custom(format!("invalid signal '{signal}'")))?;
+            let target = target
+                .map(|target| OnReloadSignalTarget::try_from(OsStr::new(&target)))
+                .transpose()
+                .map_err(D::Error::custom)?
+                .unwrap_or_default();
+            OnReloadAction::Signal { signal, target }
+        }
+        _ => {
+            return Err(D::Error::custom(
+                "on-reload must specify exactly one of command, exec or signal",
+            ));
+        }
+    };
+
+    Ok(Some(action))
+}
+
+/// A context falling back to default values for missing keys.
+///
+/// Nested mappings present in both the context and the defaults are merged recursively.
+#[derive(Debug)]
+struct WithDefaults {
+    ctx: Value,
+    defaults: Value,
+}
+
+impl Object for WithDefaults {
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        let value = self.ctx.get_item(key).ok().filter(|v| !v.is_undefined());
+        let default = self
+            .defaults
+            .get_item(key)
+            .ok()
+            .filter(|v| !v.is_undefined());
+
+        match (value, default) {
+            (Some(ctx), Some(defaults))
+                if ctx.kind() == ValueKind::Map && defaults.kind() == ValueKind::Map =>
+            {
+                Some(Value::from_object(WithDefaults { ctx, defaults }))
+            }
+            (Some(value), _) => Some(value),
+            (None, default) => default,
+        }
+    }
+
+    fn enumerate(self: &Arc<Self>) -> Enumerator {
+        let mut keys: Vec<Value> = self.ctx.try_iter().into_iter().flatten().collect();
+        for key in self.defaults.try_iter().into_iter().flatten() {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        Enumerator::Values(keys)
+    }
+}
+
+#[cfg(all(test, feature = "yaml"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_front_matter() {
+        let template = "---\nrequired: [db.host]\nmode: \"0640\"\n---\nhost={{ db.host }}\n";
+        let (front_matter, body) = FrontMatter::split(template).unwrap();
+        assert_eq!(body, "host={{ db.host }}\n");
+        assert_eq!(front_matter.required, vec!["db.host".to_string()]);
+        assert_eq!(front_matter.mode, Some(0o640));
+
+        let (front_matter, body) = FrontMatter::split("no front matter\n").unwrap();
+        assert_eq!(body, "no front matter\n");
+        assert_eq!(front_matter, FrontMatter::default());
+    }
+
+    #[test]
+    fn test_yaml_document_is_not_front_matter() {
+        let template = "---\napiVersion: v1\nkind: ConfigMap\n---\nkind: Secret\n";
+        let (front_matter, body) = FrontMatter::split(template).unwrap();
+        assert_eq!(body, template);
+        assert_eq!(front_matter, FrontMatter::default());
+    }
+
+    #[test]
+    fn test_invalid_front_matter() {
+        assert!(FrontMatter::split("---\nmode: rwx\n---\n").is_err());
+        assert!(FrontMatter::split("---\ndefaults: [1]\n---\n").is_err());
+        assert!(FrontMatter::split("---\non-reload:\n  command: a\n  signal: HUP\n---\n").is_err());
+    }
+
+    #[test]
+    fn test_on_reload() {
+        let (front_matter, _) =
+            FrontMatter::split("---\non-reload:\n  signal: HUP\n  target: nginx\n---\n").unwrap();
+        assert_eq!(
+            front_matter.on_reload,
+            Some(OnReloadAction::Signal {
+                signal: nix::sys::signal::SIGHUP,
+                target: OnReloadSignalTarget::ProcessName("nginx".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_keys() {
+        let (front_matter, _) = FrontMatter::split("---\nrequired: [a, b.c, b.d]\n---\n").unwrap();
+        let ctx = Value::from_serialize(serde_json::json!({ "a": 1, "b": { "c": 2 } }));
+        assert_eq!(front_matter.missing_keys(&ctx), vec!["b.d".to_string()]);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let (front_matter, _) =
+            FrontMatter::split("---\ndefaults:\n  a: 0\n  b:\n    c: 0\n    d: 3\n---\n").unwrap();
+        let ctx = Value::from_serialize(serde_json::json!({ "a": 1, "b": { "c": 2 } }));
+
+        let mut env = minijinja::Environment::new();
+        env.add_template("t", "{{ a }} {{ b.c }} {{ b.d }}")
+            .unwrap();
+        let rendered = env
+            .get_template("t")
+            .unwrap()
+            .render(front_matter.apply_defaults(&ctx))
+            .unwrap();
+        assert_eq!(rendered, "1 2 3");
+    }
+}
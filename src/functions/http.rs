@@ -1,21 +1,108 @@
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     fmt::Debug,
-    hash::Hash,
+    hash::{BuildHasher, Hash},
+    net::SocketAddr,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
 use tokio::sync::RwLock;
 
 use arc_swap::ArcSwapOption;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
 use encoding_rs::{Encoding, UTF_8};
+use hex::ToHex;
 use mime::Mime;
-use minijinja::{Error, ErrorKind, State, Value, value::Object};
+use minijinja::{
+    Error, ErrorKind, State, Value,
+    value::{Kwargs, Object, from_args},
+};
 use reqwest::header::{HeaderMap, HeaderValue};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::functions::ContextWithRuntime;
 
+/// Timeouts, retries and failure handling of HTTP requests.
+///
+/// The policy given on the command line serves as the default,
+/// which can be overridden using keyword arguments to `http()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestPolicy {
+    /// Maximum time to establish a connection.
+    pub connect_timeout: Duration,
+
+    /// Maximum time to wait for data from the server.
+    pub read_timeout: Duration,
+
+    /// Number of times a request is retried on connection errors, timeouts and 5xx responses.
+    pub retries: u32,
+
+    /// Delay before the first retry, doubling with each further retry.
+    pub backoff: Duration,
+
+    /// Return the last cached response if the request fails.
+    pub fallback: bool,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            retries: 0,
+            backoff: Duration::from_secs(1),
+            fallback: false,
+        }
+    }
+}
+
+impl RequestPolicy {
+    /// Override the policy with the keyword arguments given to `http()`.
+    fn with_kwargs(&self, kwargs: &Kwargs) -> Result<Self, Error> {
+        let mut policy = self.clone();
+        if let Some(timeout) = kwargs.get::<Option<Value>>("connect_timeout")? {
+            policy.connect_timeout = duration_arg(&timeout)?;
+        }
+        if let Some(timeout) = kwargs.get::<Option<Value>>("read_timeout")? {
+            policy.read_timeout = duration_arg(&timeout)?;
+        }
+        if let Some(retries) = kwargs.get::<Option<u32>>("retries")? {
+            policy.retries = retries;
+        }
+        if let Some(backoff) = kwargs.get::<Option<Value>>("backoff")? {
+            policy.backoff = duration_arg(&backoff)?;
+        }
+        if let Some(fallback) = kwargs.get::<Option<bool>>("fallback")? {
+            policy.fallback = fallback;
+        }
+        kwargs.assert_all_used()?;
+        Ok(policy)
+    }
+}
+
+/// Parse a duration given either as a number of seconds or a human-readable string like `"500ms"`.
+fn duration_arg(value: &Value) -> Result<Duration, Error> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Invalid duration {value}: {e}"),
+        )
+    };
+
+    if let Some(s) = value.as_str() {
+        return humantime::parse_duration(s).map_err(|e| invalid(&e));
+    }
+
+    f64::try_from(value.clone())
+        .map_err(|e| invalid(&e))
+        .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(|e| invalid(&e)))
+}
+
 #[derive(Default)]
 pub struct RequestCache {
     inner: RwLock<HashSet<CachableRequest>>,
@@ -28,12 +115,13 @@ impl RequestCache {
 
     async fn get_cacheable_request(
         &self,
+        profile: &str,
         method: reqwest::Method,
         url: reqwest::Url,
         headers: BTreeMap<String, String>,
         body: Vec<u8>,
     ) -> CachableRequest {
-        let new_request = CachableRequest::new(method, url, headers, body);
+        let new_request = CachableRequest::new(profile, method, url, headers, body);
 
         if let Some(cached) = self.inner.read().await.get(&new_request) {
             log::debug!("Request found in cache");
@@ -63,23 +151,189 @@ struct Response {
     text: Option<String>,
 }
 
+impl Response {
+    fn new(status: u16, headers: HashMap<String, String>, body: &[u8]) -> Self {
+        let content_type: Option<Mime> = headers
+            .get(reqwest::header::CONTENT_TYPE.as_str())
+            .and_then(|value| value.parse::<Mime>().ok());
+
+        let encoding_name = content_type
+            .as_ref()
+            .and_then(|mime| mime.get_param("charset").map(|charset| charset.as_str()))
+            .unwrap_or("utf-8");
+
+        let encoding = Encoding::for_label(encoding_name.as_bytes()).unwrap_or(UTF_8);
+
+        let text = match encoding.decode(body) {
+            (text, _, false) => Some(text.to_string()),
+            (_, _, true) => None,
+        };
+
+        Self {
+            status,
+            headers,
+            json: serde_json::from_slice(body).ok(),
+            text,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Cached {
     etag: Option<String>,
+    fetched_at: DateTime<Utc>,
+    body: Vec<u8>,
     response: Response,
 }
 
-impl From<Response> for Cached {
-    fn from(response: Response) -> Self {
-        let etag = response
-            .headers
-            .get(reqwest::header::ETAG.as_str())
-            .cloned();
-        Self { etag, response }
+impl Cached {
+    fn new(status: u16, headers: HashMap<String, String>, body: Vec<u8>) -> Self {
+        Self::fetched_at(Utc::now(), status, headers, body)
+    }
+
+    fn fetched_at(
+        fetched_at: DateTime<Utc>,
+        status: u16,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Self {
+        let etag = headers.get(reqwest::header::ETAG.as_str()).cloned();
+        let response = Response::new(status, headers, &body);
+        Self {
+            etag,
+            fetched_at,
+            body,
+            response,
+        }
+    }
+}
+
+/// A directory persisting responses across renders and restarts.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+/// A response as stored in a [DiskCache].
+#[derive(Serialize, Deserialize)]
+struct StoredResponse {
+    url: String,
+    status: u16,
+    headers: HashMap<String, String>,
+    body: String,
+    fetched_at: String,
+}
+
+impl DiskCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The file a request's response is stored in, named after a hash of the request.
+    fn path(&self, request: &CachableRequest) -> PathBuf {
+        let mut hasher = Sha256::new();
+        for part in [
+            request.profile.as_bytes(),
+            request.method.as_str().as_bytes(),
+            request.url.as_str().as_bytes(),
+        ] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        for (k, v) in &request.headers {
+            for part in [k, v] {
+                hasher.update((part.len() as u64).to_le_bytes());
+                hasher.update(part.as_bytes());
+            }
+        }
+        hasher.update((request.body.len() as u64).to_le_bytes());
+        hasher.update(&request.body);
+
+        self.dir
+            .join(format!("{}.json", hasher.finalize().encode_hex::<String>()))
+    }
+
+    /// Load the stored response to a request, if any.
+    fn load(&self, request: &CachableRequest) -> Option<Cached> {
+        let path = self.path(request);
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("Could not read cached response {path:?}: {e}");
+                return None;
+            }
+        };
+
+        let stored: StoredResponse = serde_json::from_reader(file)
+            .inspect_err(|e| log::warn!("Could not parse cached response {path:?}: {e}"))
+            .ok()?;
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(stored.body)
+            .inspect_err(|e| log::warn!("Could not decode cached response {path:?}: {e}"))
+            .ok()?;
+        let fetched_at = DateTime::parse_from_rfc3339(&stored.fetched_at)
+            .inspect_err(|e| log::warn!("Could not parse fetch time of {path:?}: {e}"))
+            .ok()?;
+
+        log::debug!("Loaded cached response to {} from {path:?}", request.url);
+        Some(Cached::fetched_at(
+            fetched_at.to_utc(),
+            stored.status,
+            stored.headers,
+            body,
+        ))
+    }
+
+    /// Store the response to a request.
+    ///
+    /// Failures are logged, as they should not prevent rendering.
+    fn store(&self, request: &CachableRequest, cached: &Cached) {
+        let path = self.path(request);
+        let stored = StoredResponse {
+            url: request.url.to_string(),
+            status: cached.response.status,
+            headers: cached.response.headers.clone(),
+            body: base64::engine::general_purpose::STANDARD.encode(&cached.body),
+            fetched_at: cached.fetched_at.to_rfc3339(),
+        };
+
+        // Responses may contain secrets, so they are only readable by the owner.
+        // They are written to a temporary file first, so a crash never leaves a partial response.
+        // Its name is unique, as other processes may share the cache directory.
+        let random = std::hash::RandomState::new().hash_one(&path);
+        let tmp = path.with_extension(format!("json.{}-{random:016x}.tmp", std::process::id()));
+        let result = std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&tmp)
+            })
+            .and_then(|file| {
+                serde_json::to_writer(&file, &stored)?;
+                // flushed to disk before the rename, which could otherwise persist first.
+                file.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&tmp, &path));
+
+        match result {
+            Ok(()) => log::debug!("Stored response to {} in {path:?}", request.url),
+            Err(e) => {
+                log::warn!("Could not store response in {path:?}: {e}");
+                let _ = std::fs::remove_file(&tmp);
+            }
+        }
     }
 }
 
 struct CachableRequest {
+    /// The name of the client profile, as responses may differ between profiles.
+    profile: String,
     method: reqwest::Method,
     url: reqwest::Url,
     headers: BTreeMap<String, String>,
@@ -91,6 +345,7 @@ impl Clone for CachableRequest {
     fn clone(&self) -> Self {
         let cached_response = ArcSwapOption::new(self.cached_response.load_full().clone());
         Self {
+            profile: self.profile.clone(),
             method: self.method.clone(),
             url: self.url.clone(),
             headers: self.headers.clone(),
@@ -102,6 +357,7 @@ impl Clone for CachableRequest {
 
 impl Hash for CachableRequest {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.profile.hash(state);
         self.method.hash(state);
         self.url.hash(state);
         self.headers.hash(state);
@@ -112,7 +368,8 @@ impl Hash for CachableRequest {
 
 impl PartialEq for CachableRequest {
     fn eq(&self, other: &Self) -> bool {
-        self.method == other.method
+        self.profile == other.profile
+            && self.method == other.method
             && self.url == other.url
             && self.headers == other.headers
             && self.body == other.body
@@ -125,6 +382,7 @@ impl Eq for CachableRequest {}
 impl Debug for CachableRequest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CachableRequest")
+            .field("profile", &self.profile)
             .field("method", &self.method)
             .field("url", &self.url)
             .field("headers", &self.headers)
@@ -134,14 +392,31 @@ impl Debug for CachableRequest {
     }
 }
 
+/// The reason a single attempt at performing a request failed.
+enum Failure {
+    /// The request could not be sent, or the response could not be received.
+    Request(reqwest::Error),
+
+    /// Any other error, which is not worth retrying.
+    Other(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Other(e)
+    }
+}
+
 impl CachableRequest {
     pub fn new(
+        profile: &str,
         method: reqwest::Method,
         url: reqwest::Url,
         headers: BTreeMap<String, String>,
         body: Vec<u8>,
     ) -> Self {
         Self {
+            profile: profile.to_owned(),
             method,
             url,
             headers,
@@ -150,7 +425,76 @@ impl CachableRequest {
         }
     }
 
-    pub async fn perform(&self) -> Result<Arc<Cached>, Error> {
+    /// Perform the request, retrying and falling back to the cached response according to the policy.
+    pub async fn perform(
+        &self,
+        client: &reqwest::Client,
+        policy: &RequestPolicy,
+        disk_cache: Option<&DiskCache>,
+    ) -> Result<Arc<Cached>, Error> {
+        if let Some(disk_cache) = disk_cache
+            && self.cached_response.load().is_none()
+            && let Some(cached) = disk_cache.load(self)
+        {
+            self.cached_response.store(Some(Arc::new(cached)));
+        }
+
+        let mut backoff = policy.backoff;
+        let mut attempt = 0;
+
+        loop {
+            let result = self.perform_once(client, disk_cache).await;
+
+            let (failed, retryable) = match result {
+                Ok(ref cached) => {
+                    let server_error = cached.response.status >= 500;
+                    (server_error, server_error)
+                }
+                Err(Failure::Request(ref e)) => (true, e.is_connect() || e.is_timeout()),
+                Err(Failure::Other(_)) => (false, false),
+            };
+
+            if retryable && attempt < policy.retries {
+                attempt += 1;
+                log::warn!(
+                    "HTTP request to {} failed, retrying in {backoff:?} ({attempt}/{})",
+                    self.url,
+                    policy.retries
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                continue;
+            }
+
+            // With a disk cache, stale responses are served if the server is unreachable.
+            let unreachable = disk_cache.is_some() && matches!(result, Err(Failure::Request(_)));
+            if failed
+                && (policy.fallback || unreachable)
+                && let Some(cached) = self.cached_response.load_full()
+            {
+                log::warn!(
+                    "HTTP request to {} failed, falling back to the response cached at {}",
+                    self.url,
+                    cached.fetched_at
+                );
+                return Ok(cached);
+            }
+
+            return result.map_err(|failure| match failure {
+                Failure::Request(e) => Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("Could not perform HTTP request: {e}"),
+                ),
+                Failure::Other(e) => e,
+            });
+        }
+    }
+
+    async fn perform_once(
+        &self,
+        client: &reqwest::Client,
+        disk_cache: Option<&DiskCache>,
+    ) -> Result<Arc<Cached>, Failure> {
         let headers = self
             .headers
             .iter()
@@ -181,45 +525,29 @@ impl CachableRequest {
             headers.insert(reqwest::header::IF_NONE_MATCH, etag);
         }
 
-        let client = reqwest::Client::new();
         let request = reqwest::Request::new(self.method.clone(), self.url.clone());
 
-        let response = reqwest::RequestBuilder::from_parts(client, request)
+        let response = reqwest::RequestBuilder::from_parts(client.clone(), request)
             .headers(headers)
             .body(self.body.clone())
             .send()
             .await
-            .map_err(|e| {
-                Error::new(
-                    ErrorKind::InvalidOperation,
-                    format!("Could not perform HTTP request: {e}"),
-                )
-            })?;
+            .map_err(Failure::Request)?;
         log::trace!("Response: {response:?}");
 
         let status = response.status();
 
         if status == reqwest::StatusCode::NOT_MODIFIED {
             log::debug!("Server returned 304 Not Modified");
-            return self.cached_response.load_full().ok_or(Error::new(
-                ErrorKind::InvalidOperation,
-                "HTTP Server returned 304 Not Modified, but no response is cached.",
-            ));
+            return self
+                .cached_response
+                .load_full()
+                .ok_or(Failure::Other(Error::new(
+                    ErrorKind::InvalidOperation,
+                    "HTTP Server returned 304 Not Modified, but no response is cached.",
+                )));
         }
 
-        let content_type: Option<Mime> = response
-            .headers()
-            .get(reqwest::header::CONTENT_TYPE)
-            .and_then(|value| value.to_str().ok())
-            .and_then(|value| value.parse::<Mime>().ok());
-
-        let encoding_name = content_type
-            .as_ref()
-            .and_then(|mime| mime.get_param("charset").map(|charset| charset.as_str()))
-            .unwrap_or("utf-8");
-
-        let encoding = Encoding::for_label(encoding_name.as_bytes()).unwrap_or(UTF_8);
-
         let headers = response
             .headers()
             .into_iter()
@@ -229,111 +557,452 @@ impl CachableRequest {
             })
             .collect();
 
-        let body = response.bytes().await.map_err(|e| {
+        let body = response.bytes().await.map_err(Failure::Request)?;
+
+        let cached = Arc::new(Cached::new(status.as_u16(), headers, body.to_vec()));
+
+        // Server errors must not replace a response that can be fallen back to.
+        if !status.is_server_error() {
+            self.cached_response.store(Some(cached.clone()));
+            if let Some(disk_cache) = disk_cache {
+                disk_cache.store(self, &cached);
+            }
+        }
+
+        Ok(cached)
+    }
+}
+
+/// Settings of the HTTP client, selectable by name in `http()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ClientProfile {
+    /// A PEM bundle of additional trusted CA certificates.
+    pub ca: Option<PathBuf>,
+
+    /// A PEM client certificate, for mutual TLS.
+    pub cert: Option<PathBuf>,
+
+    /// The PEM private key of the client certificate.
+    pub key: Option<PathBuf>,
+
+    /// Host names resolved to fixed addresses, e.g. to connect to an address while
+    /// presenting the host name of the URL as server name.
+    pub resolve: Vec<(String, SocketAddr)>,
+
+    /// The TLS server name presented to and verified for the hosts of HTTPS URLs,
+    /// in place of the host name of the URL.
+    pub sni: Option<String>,
+
+    /// A proxy URL used for all requests.
+    pub proxy: Option<String>,
+
+    /// A comma-separated list of hosts not to use the proxy for.
+    pub no_proxy: Option<String>,
+
+    /// Skip verification of server certificates.
+    pub insecure: bool,
+}
+
+impl ClientProfile {
+    /// The profile used if `http()` is not given a profile.
+    pub const DEFAULT: &str = "default";
+
+    /// Load the CA certificates and the client identity, failing if the files are unusable.
+    pub fn load(&self) -> Result<(Vec<reqwest::Certificate>, Option<reqwest::Identity>), String> {
+        let read = |path: &PathBuf| std::fs::read(path).map_err(|e| format!("{path:?}: {e}"));
+
+        let certificates = match self.ca {
+            Some(ref ca) => reqwest::Certificate::from_pem_bundle(&read(ca)?)
+                .map_err(|e| format!("{ca:?}: {e}"))?,
+            None => vec![],
+        };
+
+        let identity = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                let pem = [read(cert)?, read(key)?].join(&b'\n');
+                Some(reqwest::Identity::from_pem(&pem).map_err(|e| format!("{cert:?}: {e}"))?)
+            }
+            (Some(cert), None) => {
+                // The certificate file may contain the private key as well.
+                Some(
+                    reqwest::Identity::from_pem(&read(cert)?)
+                        .map_err(|e| format!("{cert:?}: {e}"))?,
+                )
+            }
+            (None, Some(_)) => return Err("key given without a certificate".into()),
+            (None, None) => None,
+        };
+
+        Ok((certificates, identity))
+    }
+
+    /// Build a client for requests to the given host, using the settings of the profile,
+    /// and the timeouts of the policy.
+    ///
+    /// Only clients of profiles overriding the server name depend on the host.
+    pub fn build(&self, policy: &RequestPolicy, host: &str) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(policy.connect_timeout)
+            .read_timeout(policy.read_timeout)
+            .danger_accept_invalid_certs(self.insecure);
+
+        let (certificates, identity) = self.load()?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let Some(identity) = identity {
+            builder = builder.identity(identity);
+        }
+
+        // requests are sent to the server name, see `route`, so it is resolved to the host instead.
+        if self.sni.is_some() {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            builder = builder.dns_resolver(Arc::new(ResolveAs(host.to_owned())));
+        }
+
+        for (host, addr) in &self.resolve {
+            builder = builder.resolve(host, *addr);
+        }
+
+        if let Some(ref proxy) = self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| format!("proxy {proxy}: {e}"))?
+                .no_proxy(
+                    self.no_proxy
+                        .as_deref()
+                        .and_then(reqwest::NoProxy::from_string),
+                );
+            builder = builder.proxy(proxy);
+        }
+
+        builder.build().map_err(|e| e.to_string())
+    }
+
+    /// The URL to send a request for the given URL to, and the `Host` header to send, if any.
+    ///
+    /// To present another server name, HTTPS requests are sent to the server name, using the
+    /// host of the URL as `Host` header. The client built for the host connects to the host.
+    pub fn route(&self, url: &reqwest::Url) -> (reqwest::Url, Option<String>) {
+        match self.sni {
+            Some(ref sni) if url.scheme() == "https" => {
+                let mut target = url.clone();
+                target
+                    .set_host(Some(sni))
+                    .expect("server names are validated when parsing the profile");
+                let host = url.host_str().unwrap_or_default();
+                let host = match url.port() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host.to_owned(),
+                };
+                (target, Some(host))
+            }
+            _ => (url.clone(), None),
+        }
+    }
+}
+
+/// Resolves any name to the addresses of a fixed host.
+struct ResolveAs(String);
+
+impl reqwest::dns::Resolve for ResolveAs {
+    fn resolve(&self, _: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = self.0.clone();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0)).await?;
+            Ok(Box::new(addrs.collect::<Vec<_>>().into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// The profile, host and timeouts a client was built for.
+type ClientKey = (String, Option<String>, Duration, Duration);
+
+/// The `http()` function, performing requests according to a default policy.
+#[derive(Debug)]
+pub struct HttpFunction {
+    policy: RequestPolicy,
+    disk_cache: Option<DiskCache>,
+    profiles: HashMap<String, ClientProfile>,
+
+    /// Clients shared between requests with the same profile and timeouts, and with the
+    /// same host if the profile overrides the server name.
+    clients: std::sync::Mutex<HashMap<ClientKey, reqwest::Client>>,
+}
+
+impl HttpFunction {
+    pub fn new(
+        policy: RequestPolicy,
+        disk_cache: Option<DiskCache>,
+        profiles: HashMap<String, ClientProfile>,
+    ) -> Self {
+        Self {
+            policy,
+            disk_cache,
+            profiles,
+            clients: Default::default(),
+        }
+    }
+
+    /// Get the client of the given profile for requests to the URL, creating it on first use.
+    fn client(
+        &self,
+        profile: Option<&str>,
+        policy: &RequestPolicy,
+        url: &reqwest::Url,
+    ) -> Result<reqwest::Client, Error> {
+        let name = profile.unwrap_or(ClientProfile::DEFAULT);
+        let host = url.host_str().unwrap_or_default();
+        let key = (
+            name.to_owned(),
+            self.profiles
+                .get(name)
+                .and_then(|profile| profile.sni.as_ref())
+                .map(|_| host.to_owned()),
+            policy.connect_timeout,
+            policy.read_timeout,
+        );
+
+        let mut clients = self.clients.lock().expect("lock poisoned");
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = match (self.profiles.get(name), profile) {
+            (Some(profile), _) => profile.build(policy, host),
+            (None, None) => ClientProfile::default().build(policy, host),
+            (None, Some(name)) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("Unknown HTTP client profile '{name}'"),
+                ));
+            }
+        }
+        .map_err(|e| {
             Error::new(
                 ErrorKind::InvalidOperation,
-                format!("Could not get body: {e}"),
+                format!("Could not create HTTP client for profile '{name}': {e}"),
             )
         })?;
 
-        let text = match encoding.decode(&body) {
-            (text, _, false) => Some(text.to_string()),
-            (_, _, true) => None,
+        log::debug!("Created HTTP client for profile '{name}'");
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    fn http_request(
+        &self,
+        state: &State,
+        method: &str,
+        url: &str,
+        headers: Option<&Value>,
+        body: Option<&Value>,
+        kwargs: Kwargs,
+    ) -> Result<Value, Error> {
+        let profile: Option<&str> = kwargs.get("profile")?;
+        let policy = self.policy.with_kwargs(&kwargs)?;
+        let disk_cache = self.disk_cache.as_ref();
+
+        let rt = crate::functions::get_runtime_handle(state);
+        log::debug!("HTTP Request: Method={method:?} url={url:?}");
+
+        let ctx = state
+            .lookup("$context")
+            .ok_or(Error::new(ErrorKind::InvalidOperation, "$context missing"))?;
+        let ctx = ctx
+            .downcast_object_ref::<ContextWithRuntime>()
+            .ok_or(Error::new(
+                ErrorKind::InvalidOperation,
+                "Context has wrong type",
+            ))?;
+
+        let method = method
+            .to_uppercase()
+            .parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidOperation, format!("Invalid method: {e}")))?;
+
+        let url = url
+            .parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidOperation, format!("Invalid URL: {e}")))?;
+        let client = self.client(profile, &policy, &url)?;
+
+        let mut headers: BTreeMap<String, String> = headers
+            .and_then(|headers| {
+                headers
+                    .as_object()
+                    .and_then(|obj| obj.try_iter_pairs())
+                    .map(|i| {
+                        i.filter_map(|(k, v)| match (k.as_str(), v.as_str()) {
+                            (Some(k), Some(v)) => Some((k.to_string(), v.to_string())),
+                            _ => None,
+                        })
+                        .collect()
+                    })
+            })
+            .unwrap_or_default();
+
+        let (url, host) = match self.profiles.get(profile.unwrap_or(ClientProfile::DEFAULT)) {
+            Some(profile) => profile.route(&url),
+            None => (url, None),
         };
+        if let Some(host) = host
+            && !headers.keys().any(|k| k.eq_ignore_ascii_case("host"))
+        {
+            headers.insert("host".into(), host);
+        }
 
-        let cached: Arc<Cached> = Arc::new(
-            Response {
-                status: status.as_u16(),
-                headers,
-                json: serde_json::from_slice(body.as_ref()).ok(),
-                text,
+        let body = match body {
+            Some(body) => body
+                .as_bytes()
+                .ok_or(Error::new(ErrorKind::InvalidOperation, "Wrong body type!"))?
+                .to_owned(),
+            None => vec![],
+        };
+
+        rt.block_on(async {
+            if !ctx.has::<RequestCache>().await {
+                ctx.put(RequestCache::default()).await;
             }
-            .into(),
-        );
 
-        self.cached_response.store(Some(cached.clone()));
+            let cache = ctx
+                .get_ref::<RequestCache>()
+                .await
+                .expect("Cache is missing.");
+
+            let request = cache
+                .get_cacheable_request(
+                    profile.unwrap_or(ClientProfile::DEFAULT),
+                    method,
+                    url,
+                    headers,
+                    body,
+                )
+                .await;
+
+            std::mem::drop(cache);
 
-        Ok(cached)
+            let cached = request.perform(&client, &policy, disk_cache).await?;
+
+            let cache = ctx
+                .get_mut::<RequestCache>()
+                .await
+                .expect("Cache is missing.");
+            cache.insert(request).await;
+            std::mem::drop(cache);
+
+            Ok(Value::from_serialize(&cached.response))
+        })
     }
 }
 
-pub fn http_request(
-    state: &State,
-    method: &str,
-    url: &str,
-    headers: Option<&Value>,
-    body: Option<&Value>,
-) -> Result<Value, Error> {
-    let rt = crate::functions::get_runtime_handle(state);
-    log::debug!("HTTP Request: Method={method:?} url={url:?}");
-
-    let ctx = state
-        .lookup("$context")
-        .ok_or(Error::new(ErrorKind::InvalidOperation, "$context missing"))?;
-    let ctx = ctx
-        .downcast_object_ref::<ContextWithRuntime>()
-        .ok_or(Error::new(
-            ErrorKind::InvalidOperation,
-            "Context has wrong type",
-        ))?;
-
-    let method = method
-        .to_uppercase()
-        .parse()
-        .map_err(|e| Error::new(ErrorKind::InvalidOperation, format!("Invalid method: {e}")))?;
-
-    let url = url
-        .parse()
-        .map_err(|e| Error::new(ErrorKind::InvalidOperation, format!("Invalid URL: {e}")))?;
-
-    let headers = headers
-        .and_then(|headers| {
-            headers
-                .as_object()
-                .and_then(|obj| obj.try_iter_pairs())
-                .map(|i| {
-                    i.filter_map(|(k, v)| match (k.as_str(), v.as_str()) {
-                        (Some(k), Some(v)) => Some((k.to_string(), v.to_string())),
-                        _ => None,
-                    })
-                    .collect()
-                })
-        })
-        .unwrap_or_default();
-
-    let body = match body {
-        Some(body) => body
-            .as_bytes()
-            .ok_or(Error::new(ErrorKind::InvalidOperation, "Wrong body type!"))?
-            .to_owned(),
-        None => vec![],
-    };
+impl Object for HttpFunction {
+    fn call(self: &Arc<Self>, state: &State<'_, '_>, args: &[Value]) -> Result<Value, Error> {
+        let (method, url, headers, body, kwargs) = from_args(args)?;
+        self.http_request(state, method, url, headers, body, kwargs)
+    }
+}
 
-    rt.block_on(async {
-        if !ctx.has::<RequestCache>().await {
-            ctx.put(RequestCache::default()).await;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sni() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // the client hello is captured, and the handshake fails as the connection is closed.
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut hello = vec![0; 4096];
+            let len = std::io::Read::read(&mut stream, &mut hello).unwrap();
+            hello.truncate(len);
+            hello
+        });
+
+        let profile = ClientProfile {
+            sni: Some("config.internal".into()),
+            ..Default::default()
+        };
+        let url: reqwest::Url = format!("https://127.0.0.1:{port}/app").parse().unwrap();
+        let (target, host) = profile.route(&url);
+        assert_eq!(
+            target.as_str(),
+            format!("https://config.internal:{port}/app")
+        );
+        assert_eq!(host, Some(format!("127.0.0.1:{port}")));
 
-        let cache = ctx
-            .get_ref::<RequestCache>()
-            .await
-            .expect("Cache is missing.");
+        let client = profile
+            .build(&RequestPolicy::default(), url.host_str().unwrap())
+            .unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(async { client.get(target).send().await });
+        assert!(result.is_err());
 
-        let request = cache
-            .get_cacheable_request(method, url, headers, body)
-            .await;
+        let hello = server.join().unwrap();
+        assert!(hello.windows(15).any(|w| w == b"config.internal"));
 
-        std::mem::drop(cache);
+        // plain HTTP requests are left alone.
+        let url: reqwest::Url = "http://127.0.0.1/app".parse().unwrap();
+        assert_eq!(profile.route(&url), (url, None));
+    }
 
-        let cached = request.perform().await?;
+    #[test]
+    fn test_policy_kwargs() {
+        let kwargs = Kwargs::from_iter([
+            ("connect_timeout", Value::from(2.5)),
+            ("read_timeout", Value::from("500ms")),
+            ("retries", Value::from(3)),
+            ("fallback", Value::from(true)),
+        ]);
+        let policy = RequestPolicy::default().with_kwargs(&kwargs).unwrap();
+        assert_eq!(policy.connect_timeout, Duration::from_millis(2500));
+        assert_eq!(policy.read_timeout, Duration::from_millis(500));
+        assert_eq!(policy.retries, 3);
+        assert_eq!(policy.backoff, RequestPolicy::default().backoff);
+        assert!(policy.fallback);
+
+        let kwargs = Kwargs::from_iter([("retry", Value::from(3))]);
+        assert!(RequestPolicy::default().with_kwargs(&kwargs).is_err());
+
+        let kwargs = Kwargs::from_iter([("backoff", Value::from("soon"))]);
+        assert!(RequestPolicy::default().with_kwargs(&kwargs).is_err());
+    }
 
-        let cache = ctx
-            .get_mut::<RequestCache>()
-            .await
-            .expect("Cache is missing.");
-        cache.insert(request).await;
-        std::mem::drop(cache);
+    #[test]
+    fn test_disk_cache_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk_cache = DiskCache::new(dir.path());
+        let request = |url: &str| {
+            CachableRequest::new(
+                ClientProfile::DEFAULT,
+                reqwest::Method::GET,
+                url.parse().unwrap(),
+                BTreeMap::from([("accept".into(), "application/json".into())]),
+                vec![],
+            )
+        };
 
-        Ok(Value::from_serialize(&cached.response))
-    })
+        let headers = HashMap::from([
+            ("etag".into(), "\"abc\"".into()),
+            ("content-type".into(), "application/json".into()),
+        ]);
+        let cached = Cached::new(200, headers, br#"{"a": 1}"#.to_vec());
+        disk_cache.store(&request("http://localhost/a"), &cached);
+        disk_cache.store(&request("http://localhost/a"), &cached);
+        // no temporary files are left behind.
+        let stored = std::fs::read_dir(&dir).unwrap().count();
+
+        let loaded = disk_cache.load(&request("http://localhost/a")).unwrap();
+        assert!(disk_cache.load(&request("http://localhost/b")).is_none());
+
+        // responses fetched using another profile are not served.
+        let mut other = request("http://localhost/a");
+        other.profile = "internal".into();
+        assert!(disk_cache.load(&other).is_none());
+
+        assert_eq!(stored, 1);
+        assert_eq!(loaded.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(loaded.fetched_at, cached.fetched_at);
+        assert_eq!(loaded.response.status, 200);
+        assert_eq!(loaded.response.json, Some(serde_json::json!({ "a": 1 })));
+    }
 }
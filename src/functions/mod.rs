@@ -110,7 +110,36 @@ pub fn get_runtime_handle(state: &State) -> tokio::runtime::Handle {
 #[cfg(feature = "http")]
 mod http;
 
-pub fn register(#[allow(unused_variables)] env: &mut Environment) {
+#[cfg(feature = "http")]
+pub use http::{ClientProfile, DiskCache, RequestPolicy};
+
+/// Options for the functions available in templates.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The default policy of the `http()` function.
+    #[cfg(feature = "http")]
+    pub http: RequestPolicy,
+
+    /// The directory the `http()` function persists responses in.
+    #[cfg(feature = "http")]
+    pub http_cache: Option<DiskCache>,
+
+    /// The client profiles selectable in the `http()` function.
+    #[cfg(feature = "http")]
+    pub http_profiles: HashMap<String, ClientProfile>,
+}
+
+pub fn register(
+    #[allow(unused_variables)] env: &mut Environment,
+    #[allow(unused_variables)] options: Options,
+) {
     #[cfg(feature = "http")]
-    env.add_function("http", http::http_request);
+    env.add_global(
+        "http",
+        Value::from_object(http::HttpFunction::new(
+            options.http,
+            options.http_cache,
+            options.http_profiles,
+        )),
+    );
 }
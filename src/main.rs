@@ -7,19 +7,34 @@ use datasource::SourceRegistry;
 use error::{Error, Result};
 
 pub mod datasource;
+pub mod dump;
 pub mod filters;
+pub mod frontmatter;
 pub mod functions;
 pub mod plan;
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod watch;
 
 pub mod reload;
 use futures::FutureExt;
-use reload::OnReload;
+use reload::{OnReload, OnReloadAction, OnReloadSignalTarget, Reload, UpdatedFile};
 
+pub mod status;
+use status::StatusFile;
+
+pub mod supervise;
+use supervise::Supervisor;
+
+pub mod systemd;
+use systemd::Notifier;
+
+use nix::sys::signal::Signal;
 use nix::unistd::{ForkResult, execv, fork};
-use std::{ffi::CString, ops::DerefMut, sync::Arc};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::{collections::HashMap, ffi::CString, ops::DerefMut, path::PathBuf, sync::Arc};
 use tokio::sync::Mutex;
-use watch::WatcherRegistry;
+use watch::{SignalWatcher, WatcherRegistry};
 
 fn fork_and_exec_in_parent(path: &CString, args: &[CString]) {
     let fork = unsafe { fork() };
@@ -52,22 +67,50 @@ fn run_oneshot(
 
     let _guard = runtime.enter();
 
-    let value: serde_json::Value = runtime.block_on(sources.as_figment())?.extract()?;
+    let value = runtime.block_on(sources.context())?;
     let ctx = functions::capture_runtime_handle(value);
     plan.try_execute(env, &ctx, dry_run, diff)?;
 
     Ok(())
 }
 
+fn dump_context(sources: &SourceRegistry, format: dump::DumpFormat, explain: bool) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let figment = runtime.block_on(sources.as_figment())?;
+    print!("{}", dump::dump_context(&figment, format, explain)?);
+
+    Ok(())
+}
+
+/// Run the executable as a supervised child until it exits, returning its exit code.
+fn run_supervised(supervisor: &Supervisor) -> Result<i32> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async {
+        supervisor.start()?;
+        Ok(supervisor.supervise(Supervisor::FORWARDED_SIGNALS).await)
+    })
+}
+
+/// Watch for changes until shut down, returning the exit code of the supervised child, if any.
+#[allow(clippy::too_many_arguments)]
 fn run_watch<I: Iterator<Item = Box<dyn crate::watch::Watch + Sync + Send>>>(
     plan: plan::Plan,
     mut sources: SourceRegistry,
     watchers: I,
     env: minijinja::Environment<'static>,
     on_reload: &OnReload,
+    supervisor: Option<Arc<Supervisor>>,
+    status: Option<Arc<StatusFile>>,
+    systemd: Option<Arc<Notifier>>,
     dry_run: bool,
     diff: bool,
-) {
+) -> Option<i32> {
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(num_cpus::get())
         .thread_name("contemplate-worker")
@@ -80,62 +123,227 @@ fn run_watch<I: Iterator<Item = Box<dyn crate::watch::Watch + Sync + Send>>>(
         .unwrap();
 
     log::info!("Starting to watch for changes");
+    if let Some(ref status) = status {
+        status.update(|status| status.rendered_at = Some(status::now()));
+    }
     let plan = Arc::new(Mutex::new(plan));
     let env = Arc::new(Mutex::new(env));
     let on_reload = Arc::new(Mutex::new(on_reload));
+    let template_hooks = Arc::new(Mutex::new(HashMap::<PathBuf, OnReload>::new()));
 
+    let reload_templates = Arc::new(AtomicBool::new(false));
+    let signal_watcher = SignalWatcher::new(reload_templates.clone());
+    let watchers = watchers.chain(std::iter::once(Box::new(signal_watcher) as _));
     let mut watchers = WatcherRegistry::new(&mut sources, watchers);
+    if let Some(ref systemd) = systemd {
+        let notifier = systemd.clone();
+        watchers = watchers.with_ready(move || notifier.ready("Rendered, watching for changes"));
+        // pinged by the watch loop, so systemd notices a render or hook hanging.
+        if let Some(interval) = systemd.watchdog_interval() {
+            let notifier = systemd.clone();
+            watchers = watchers.with_heartbeat(interval / 2, move || notifier.watchdog());
+        }
+    }
 
-    let task = watchers.watch(|sources| {
-        let plan = plan.clone();
-        let env = env.clone();
-        let on_reload = on_reload.clone();
-        async move {
-            let Ok(value) = sources
-                .as_figment()
-                .await
-                .unwrap()
-                .extract::<serde_json::Value>()
-                .map_err(|e| log::warn!("Error reading data: {e}. Not reloading."))
-            else {
-                return;
-            };
-            let ctx = functions::capture_runtime_handle(value);
+    // when supervising, shut down once the child exits, and forward signals to it instead.
+    let shutdown = match supervisor {
+        Some(ref supervisor) => {
+            let supervisor = supervisor.clone();
+            async move {
+                supervisor.wait().await;
+            }
+            .boxed()
+        }
+        None => shutdown_signal().boxed(),
+    };
 
+    let sequence = Arc::new(AtomicU64::new(0));
+    let task = watchers.watch(
+        |sources, trigger| {
             let plan = plan.clone();
+            let sequence = sequence.clone();
             let env = env.clone();
-            let updated_files = tokio::task::spawn_blocking(move || {
-                let mut plan = plan.blocking_lock();
-                let mut env = env.blocking_lock();
-                plan.execute(env.deref_mut(), &ctx, dry_run, diff)
-                    .into_iter()
-                    .map(|op| op.dest.path())
-                    .map(|cow| cow.into_owned())
-                    .collect::<Vec<_>>()
-            })
-            .await
-            .unwrap();
-            // do not fire on-reload when nothing was updated.
-            if updated_files.is_empty() {
-                return;
-            }
-            if let Err(e) = on_reload
-                .lock()
-                .await
-                .execute(updated_files.into_iter())
+            let on_reload = on_reload.clone();
+            let template_hooks = template_hooks.clone();
+            let reload_templates = reload_templates.clone();
+            let status = status.clone();
+            let systemd = systemd.clone();
+            async move {
+                if let Some(ref systemd) = systemd {
+                    systemd.reloading();
+                }
+                let value = match sources.context().await {
+                    Ok(value) => value,
+                    Err(e) => {
+                        log::warn!("Error reading data: {e}. Not reloading.");
+                        if let Some(status) = status {
+                            status.update(|status| status.error = Some(e.to_string()));
+                        }
+                        if let Some(systemd) = systemd {
+                            systemd.ready(&format!("Not rendered: {e}"));
+                        }
+                        return;
+                    }
+                };
+                let ctx = functions::capture_runtime_handle(value);
+
+                let plan = plan.clone();
+                let env = env.clone();
+                let reload_templates = reload_templates.swap(false, Ordering::SeqCst);
+                let updated_files = tokio::task::spawn_blocking(move || {
+                    let mut plan = plan.blocking_lock();
+                    let mut env = env.blocking_lock();
+                    if reload_templates {
+                        match plan.reload_templates(env.deref_mut()) {
+                            Ok(()) => log::info!("Re-read templates"),
+                            Err(e) => log::error!("Could not re-read templates, keeping them: {e}"),
+                        }
+                    }
+                    // hash the destinations before rendering, to tell hooks what changed.
+                    let old_hashes = plan
+                        .destination_files()
+                        .map(|path| (path.to_owned(), reload::sha256(path)))
+                        .collect::<HashMap<_, _>>();
+                    plan.execute(env.deref_mut(), &ctx, dry_run, diff)
+                        .into_iter()
+                        .map(|op| {
+                            let path = op.dest.path().into_owned();
+                            let file = UpdatedFile {
+                                old_sha256: old_hashes.get(&path).cloned().flatten(),
+                                new_sha256: reload::sha256(&path),
+                                path,
+                            };
+                            let hook = op.source.get_cached_front_matter().on_reload.clone();
+                            (file, hook)
+                        })
+                        .collect::<Vec<_>>()
+                })
                 .await
-            {
-                log::warn!("On-reload notification failed: {e:?}");
-            };
+                .unwrap();
+                if let Some(status) = status {
+                    status.update(|status| {
+                        status.rendered_at = Some(status::now());
+                        status.updated_files =
+                            updated_files.iter().map(|(f, _)| f.path.clone()).collect();
+                        status.error = None;
+                    });
+                }
+                if let Some(systemd) = systemd {
+                    systemd.ready(&format!(
+                        "Rendered at {}, updated files: {}",
+                        status::now(),
+                        updated_files.len()
+                    ));
+                }
+                // do not fire on-reload when nothing was updated.
+                if updated_files.is_empty() {
+                    return;
+                }
+                let (files, hooks): (Vec<_>, Vec<_>) = updated_files.into_iter().unzip();
+                let reload = Reload {
+                    sequence: sequence.fetch_add(1, Ordering::SeqCst) + 1,
+                    trigger,
+                    files,
+                };
+
+                // hooks declared in the front matter of the updated templates.
+                let on_reload = on_reload.lock().await;
+                let mut template_hooks = template_hooks.lock().await;
+                for (file, action) in reload.files.iter().zip(hooks) {
+                    let Some(action) = action else {
+                        continue;
+                    };
+                    let path = &file.path;
+                    if let Err(e) = template_hooks
+                        .entry(path.clone())
+                        .or_insert_with(|| on_reload.for_action(action, path.to_string_lossy()))
+                        .execute(&reload.only(path))
+                        .await
+                    {
+                        log::warn!("On-reload notification for {path:?} failed: {e:?}");
+                    }
+                }
+                std::mem::drop(template_hooks);
+
+                if let Err(e) = on_reload.execute(&reload).await {
+                    log::warn!("On-reload notification failed: {e:?}");
+                };
+            }
+            .boxed()
+        },
+        shutdown,
+    );
+
+    let exit_code = runtime.block_on(async {
+        let supervise = match supervisor {
+            Some(ref supervisor) => {
+                if let Err(e) = supervisor.start() {
+                    log::error!("Could not start the supervised child: {e}");
+                    return Some(1);
+                }
+                // SIGHUP and SIGUSR1 are handled by the signal watcher.
+                let forward = Supervisor::FORWARDED_SIGNALS
+                    .iter()
+                    .copied()
+                    .filter(|signal| !matches!(signal, Signal::SIGHUP | Signal::SIGUSR1))
+                    .collect::<Vec<_>>();
+                let supervisor = supervisor.clone();
+                Some(tokio::spawn(
+                    async move { supervisor.supervise(&forward).await },
+                ))
+            }
+            None => None,
+        };
+        task.await;
+        if let Some(ref systemd) = systemd {
+            systemd.stopping();
+        }
+
+        // let running hooks finish, rather than orphaning them.
+        on_reload
+            .lock()
+            .await
+            .shutdown(OnReload::SHUTDOWN_TIMEOUT)
+            .await;
+        for hook in template_hooks.lock().await.values() {
+            hook.shutdown(OnReload::SHUTDOWN_TIMEOUT).await;
+        }
+
+        match supervise {
+            Some(supervise) => Some(supervise.await.unwrap_or(1)),
+            None => None,
         }
-        .boxed()
     });
+    log::info!("Stopped watching for changes");
+    exit_code
+}
+
+/// Complete once SIGTERM or SIGINT is received.
+async fn shutdown_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
 
-    runtime.block_on(task);
+    let (Ok(mut terminate), Ok(mut interrupt)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        log::error!("Could not install the signal handlers for graceful shutdown");
+        return futures::future::pending().await;
+    };
+
+    let name = match futures::future::select(
+        std::pin::pin!(terminate.recv()),
+        std::pin::pin!(interrupt.recv()),
+    )
+    .await
+    {
+        futures::future::Either::Left(_) => "SIGTERM",
+        futures::future::Either::Right(_) => "SIGINT",
+    };
+    log::info!("Received {name}, shutting down");
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::new().unwrap_or_else(|e| match e {
+    let mut cli = Cli::new().unwrap_or_else(|e| match e {
         Error::ClapError(e) => e.exit(),
         _ => unreachable!(),
     });
@@ -147,8 +355,17 @@ fn main() -> Result<()> {
 
     cli.generate_shell_completions();
 
-    let sources = cli.sources();
+    let sources = cli.sources()?;
     log::debug!("Sources: {sources:?}");
+
+    if let Some(format) = cli.dump_context() {
+        if let Err(e) = dump_context(&sources, format, cli.explain()) {
+            log::error!("Error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let mut plan = cli.plan();
     log::debug!("Plan: {plan:?}");
 
@@ -158,7 +375,7 @@ fn main() -> Result<()> {
         env.set_loader(minijinja::path_loader(load_path));
     }
     filters::register(&mut env);
-    functions::register(&mut env);
+    functions::register(&mut env, cli.function_options()?);
     if let Err(e) = plan.ensure_cached(&mut env) {
         log::error!("Error caching templates: {e}");
         std::process::exit(1);
@@ -183,21 +400,68 @@ fn main() -> Result<()> {
                 .map_err(|e| log::error!("Failed to daemonize: {e}"));
         }
 
-        if let Some((path, args)) = cli.and_then_exec() {
-            fork_and_exec_in_parent(&path, &args);
-        }
+        // the process group of the child can only be signaled if it has one of its own.
+        let actions = cli.on_reload()?;
+        let process_group = actions
+            .iter()
+            .chain(
+                plan.iter()
+                    .filter_map(|op| op.source.get_cached_front_matter().on_reload.as_ref()),
+            )
+            .any(|action| {
+                matches!(
+                    action,
+                    OnReloadAction::Signal {
+                        target: OnReloadSignalTarget::ParentGroup,
+                        ..
+                    }
+                )
+            });
+
+        // without supervising, the executed program is the main process of a service.
+        let systemd = match cli.and_then_exec() {
+            Some(_) if !cli.supervise() => None,
+            _ => Notifier::from_env().map(Arc::new),
+        };
+
+        let supervisor = match cli.and_then_exec() {
+            Some((path, args)) if cli.supervise() => Some(Arc::new(
+                Supervisor::new(path, args).with_process_group(process_group),
+            )),
+            Some((path, args)) => {
+                fork_and_exec_in_parent(&path, &args);
+                None
+            }
+            None => None,
+        };
 
-        let on_reload: OnReload = cli.on_reload()?.into();
-        run_watch(
+        let status = cli
+            .status_file()
+            .map(|path| Arc::new(StatusFile::new(path)));
+        let on_reload = OnReload::from(actions)
+            .with_supervisor(supervisor.clone())
+            .with_policy(cli.hook_policy())
+            .with_status(status.clone());
+        let exit_code = run_watch(
             plan,
             sources,
             cli.watchers().into_iter(),
             env,
             &on_reload,
+            supervisor,
+            status,
+            systemd,
             dry_run,
             diff,
         );
+        if let Some(exit_code) = exit_code {
+            std::process::exit(exit_code);
+        }
     } else if let Some((path, args)) = cli.and_then_exec() {
+        if cli.supervise() {
+            let exit_code = run_supervised(&Supervisor::new(path, args))?;
+            std::process::exit(exit_code);
+        }
         execv(&path, &args)?;
     }
 
@@ -3,10 +3,12 @@ use colored::Colorize;
 use minijinja::{Environment, Template};
 
 use crate::error::{Error, Result};
+use crate::frontmatter::FrontMatter;
 use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::fs::{File, OpenOptions};
 use std::io::{self, IsTerminal, Read, Seek, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
@@ -25,6 +27,7 @@ pub enum TemplateSource {
     Cached {
         name: PathBuf,
         contains_trailing_newline: bool,
+        front_matter: FrontMatter,
     },
 }
 
@@ -64,13 +67,17 @@ impl TemplateSource {
             TemplateSource::Cached { .. } => return Ok(()),
         };
 
+        let (front_matter, template) = FrontMatter::split(&template)
+            .map_err(|e| Error::InvalidFrontMatter(name.clone(), e))?;
+
         let template_name = name.to_string_lossy().to_string();
         let contains_trailing_newline = template.chars().last().map(|c| c == '\n').unwrap_or(false);
-        env.add_template_owned(template_name, template)?;
+        env.add_template_owned(template_name, template.to_owned())?;
 
         *self = TemplateSource::Cached {
             name,
             contains_trailing_newline,
+            front_matter,
         };
 
         Ok(())
@@ -101,6 +108,17 @@ impl TemplateSource {
         }
     }
 
+    /// Returns the front matter of the original template
+    ///
+    /// # Panics
+    /// Panics if this template is not [cached](TemplateSource::Cached).
+    pub fn get_cached_front_matter(&self) -> &FrontMatter {
+        match self {
+            TemplateSource::Cached { front_matter, .. } => front_matter,
+            _ => panic!("get_cached_front_matter called on a non-cached template"),
+        }
+    }
+
     pub fn get_template<'env, 'source>(
         &self,
         env: &'env Environment<'source>,
@@ -190,9 +208,15 @@ impl TemplateDestination {
     /// Write the template to the destination
     ///
     /// Will only write to the destination if it would be changed.
+    /// If `mode` is given, the permissions of the destination are set before writing to it.
     /// If `log_diff` is true, also write a diff to the standard error.
     /// Returns true if the destination was changed.
-    pub fn write_templated(&self, templated: String, log_diff: bool) -> Result<bool> {
+    pub fn write_templated(
+        &self,
+        templated: String,
+        mode: Option<u32>,
+        log_diff: bool,
+    ) -> Result<bool> {
         let ret = match self {
             TemplateDestination::FileSystem(path) => {
                 let mut f = OpenOptions::new()
@@ -200,8 +224,14 @@ impl TemplateDestination {
                     .write(true)
                     .create(true)
                     .truncate(false)
+                    .mode(mode.unwrap_or(0o666))
                     .open(path)?;
 
+                // the umask may have restricted a new file further, or the file existed already.
+                if let Some(mode) = mode {
+                    set_mode(path, &f, mode)?;
+                }
+
                 if self.diff(path, &mut f, &templated, log_diff)? {
                     f.set_len(0)?;
                     f.write_all(templated.as_bytes())?;
@@ -220,6 +250,18 @@ impl TemplateDestination {
     }
 }
 
+/// Set the permissions of an open file, if they differ.
+fn set_mode(path: &Path, file: &File, mode: u32) -> Result<()> {
+    let mut permissions = file.metadata()?.permissions();
+    if permissions.mode() & 0o7777 != mode {
+        log::debug!("Setting mode of {path:?} to {mode:o}");
+        permissions.set_mode(mode);
+        file.set_permissions(permissions)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct TemplateOperation {
     pub source: TemplateSource,
@@ -309,6 +351,22 @@ impl TemplateOperation {
         Ok(())
     }
 
+    /// Ensure all keys required by the template are present in the context.
+    pub fn check_required(&mut self, env: &mut Environment, ctx: &minijinja::Value) -> Result<()> {
+        self.ensure_cached(env)?;
+
+        let missing = self.source.get_cached_front_matter().missing_keys(ctx);
+        if !missing.is_empty() {
+            let template = self.source.get_cached_name().into_owned().into();
+            return Err(Error::MissingRequiredKeys {
+                template,
+                keys: missing,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Apply a template operation.
     ///
     /// If `dry_run` is specified, no change will be made.
@@ -321,7 +379,11 @@ impl TemplateOperation {
         dry_run: bool,
         log_diff: bool,
     ) -> Result<bool> {
-        self.ensure_cached(env)?;
+        self.check_required(env, ctx)?;
+
+        let front_matter = self.source.get_cached_front_matter();
+        let ctx = front_matter.apply_defaults(ctx);
+        let mode = front_matter.mode;
 
         let mut templated = self.source.get_template(env)?.render(ctx)?;
 
@@ -332,7 +394,7 @@ impl TemplateOperation {
         let mut ret = false;
         if !dry_run {
             self.do_backup()?;
-            ret = self.dest.write_templated(templated, log_diff)?;
+            ret = self.dest.write_templated(templated, mode, log_diff)?;
         }
 
         Ok(ret)
@@ -356,6 +418,14 @@ impl Plan {
         self.operations.push(TemplateOperation::new(source, dest));
     }
 
+    /// The files templates are rendered to.
+    pub fn destination_files(&self) -> impl Iterator<Item = &Path> {
+        self.operations.iter().filter_map(|op| match op.dest {
+            TemplateDestination::FileSystem(ref path) => Some(path.as_path()),
+            TemplateDestination::StdOut => None,
+        })
+    }
+
     pub fn ensure_cached(&mut self, env: &mut Environment) -> Result<()> {
         for op in self.operations.iter_mut() {
             op.ensure_cached(env)?;
@@ -406,6 +476,11 @@ impl Plan {
         dry_run: bool,
         log_diff: bool,
     ) -> Result<Vec<&TemplateOperation>> {
+        // fail before writing anything if any template lacks required keys.
+        for operation in self.operations.iter_mut() {
+            operation.check_required(env, ctx)?;
+        }
+
         let changed = self
             .operations
             .iter_mut()
@@ -424,6 +499,40 @@ impl Plan {
         Ok(changed)
     }
 
+    /// Re-read all templates from the file system, e.g. after they were edited.
+    ///
+    /// Templates read from standard input or rendered in-place can't be re-read, and are kept.
+    /// Templates loaded from the additional templates directory are reloaded on demand.
+    /// If any template can't be read, no template is replaced.
+    pub fn reload_templates(&mut self, env: &mut Environment<'static>) -> Result<()> {
+        let mut staged = env.clone();
+        staged.clear_templates();
+
+        let mut sources = Vec::with_capacity(self.operations.len());
+        for op in self.operations.iter() {
+            let name = op.source.get_cached_name();
+            let path = Path::new(name.as_ref());
+            let source = if name != "-" && op.dest.path() != path {
+                log::debug!("Re-reading template {path:?}");
+                let mut source = TemplateSource::FileSystem(path.to_owned());
+                source.ensure_cached(&mut staged)?;
+                source
+            } else {
+                let template = env.get_template(&name)?.source().to_owned();
+                staged.add_template_owned(name.into_owned(), template)?;
+                op.source.clone()
+            };
+            sources.push(source);
+        }
+
+        for (op, source) in self.operations.iter_mut().zip(sources) {
+            op.source = source;
+        }
+        *env = staged;
+
+        Ok(())
+    }
+
     pub fn iter(&self) -> std::slice::Iter<'_, TemplateOperation> {
         self.operations.iter()
     }
@@ -1,147 +1,1227 @@
-use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
-use std::path::Path;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::error::Result;
+use crate::status::{HookState, HookStatus, StatusFile};
+use crate::supervise::Supervisor;
+use hex::ToHex;
 use itertools::Itertools;
-use nix::sys::signal::{SIGINT, Signal, kill};
-use nix::unistd::Pid;
-use sysinfo::System;
-use tokio::process::{Child, Command};
+use nix::sys::signal::{SIGINT, SIGKILL, Signal, kill, killpg};
+use nix::unistd::{Pid, getpgid, getpgrp};
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sysinfo::{Process, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+use tokio::process::Command;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
 pub enum OnReloadSignalTarget {
     Pid(Pid),
+
+    /// Processes whose name contains the given name.
     ProcessName(OsString),
+
+    /// Processes whose executable has exactly the given name.
+    Executable(OsString),
+
+    /// Processes whose command line, joined by spaces, matches the regular expression.
+    Cmdline(String),
+
+    /// The PID in the given file, read whenever signaling.
+    PidFile(PathBuf),
+
+    /// The processes in the given cgroup and its descendants, relative to the cgroup v2 root.
+    Cgroup(PathBuf),
+
     #[default]
     Parent,
+
+    /// The process group of the supervised child.
+    ParentGroup,
 }
 
-impl From<&OsStr> for OnReloadSignalTarget {
-    fn from(s: &OsStr) -> Self {
+impl TryFrom<&OsStr> for OnReloadSignalTarget {
+    type Error = String;
+
+    fn try_from(s: &OsStr) -> std::result::Result<Self, Self::Error> {
+        let prefixed = |prefix: &str| {
+            s.as_bytes()
+                .strip_prefix(prefix.as_bytes())
+                .map(OsStr::from_bytes)
+        };
+
         if s == OsStr::new(":parent") {
-            return Self::Parent;
+            return Ok(Self::Parent);
+        }
+
+        if s == OsStr::new(":parent-group") {
+            return Ok(Self::ParentGroup);
         }
 
         if let Some(pid) = s.to_str().and_then(|s| s.parse().ok()) {
-            return Self::Pid(Pid::from_raw(pid));
+            return Ok(Self::Pid(positive_pid(pid)?));
+        }
+
+        if let Some(path) = prefixed("pidfile:") {
+            return Ok(Self::PidFile(path.into()));
+        }
+
+        if let Some(path) = prefixed("cgroup:") {
+            return Ok(Self::Cgroup(path.into()));
+        }
+
+        if let Some(name) = prefixed("exe:") {
+            return Ok(Self::Executable(name.to_owned()));
+        }
+
+        if let Some(pattern) = prefixed("cmdline:") {
+            let pattern = pattern
+                .to_str()
+                .ok_or_else(|| format!("invalid command line pattern {pattern:?}"))?;
+            Regex::new(pattern).map_err(|e| format!("invalid command line pattern: {e}"))?;
+            return Ok(Self::Cmdline(pattern.to_owned()));
         }
 
-        Self::ProcessName(s.to_owned())
+        Ok(Self::ProcessName(s.to_owned()))
     }
 }
 
+/// Reject PIDs which `kill` would treat as process groups, or as every process.
+fn positive_pid(pid: i32) -> std::result::Result<Pid, String> {
+    if pid <= 0 {
+        return Err(format!("invalid PID {pid}"));
+    }
+    Ok(Pid::from_raw(pid))
+}
+
+/// Where cgroup v2 is mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+impl OnReloadSignalTarget {
+    /// The processes matching a target other than the parent, excluding contemplate itself.
+    fn processes(&self) -> std::result::Result<Vec<Pid>, String> {
+        let pids = match self {
+            Self::Pid(pid) => vec![*pid],
+            Self::ProcessName(name) => {
+                let name = name.to_string_lossy();
+                find_processes(ProcessRefreshKind::nothing(), |process| {
+                    process.name().to_string_lossy().contains(&*name)
+                })
+            }
+            Self::Executable(name) => find_processes(
+                ProcessRefreshKind::nothing().with_exe(UpdateKind::OnlyIfNotSet),
+                |process| {
+                    process.name() == name
+                        || process.exe().and_then(Path::file_name) == Some(name.as_os_str())
+                },
+            ),
+            Self::Cmdline(pattern) => {
+                let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+                find_processes(
+                    ProcessRefreshKind::nothing().with_cmd(UpdateKind::OnlyIfNotSet),
+                    |process| {
+                        let cmdline = process
+                            .cmd()
+                            .iter()
+                            .map(|arg| arg.to_string_lossy())
+                            .join(" ");
+                        regex.is_match(&cmdline)
+                    },
+                )
+            }
+            Self::PidFile(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("could not read the pidfile {path:?}: {e}"))?;
+                let pid = content
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("the pidfile {path:?} does not contain a PID"))?;
+                let pid = positive_pid(pid)
+                    .map_err(|e| format!("the pidfile {path:?} is not usable: {e}"))?;
+                vec![pid]
+            }
+            Self::Cgroup(path) => {
+                let path = path.strip_prefix("/").unwrap_or(path);
+                cgroup_processes(&Path::new(CGROUP_ROOT).join(path))?
+            }
+            Self::Parent | Self::ParentGroup => vec![],
+        };
+
+        Ok(pids.into_iter().filter(|pid| *pid != Pid::this()).collect())
+    }
+}
+
+/// The processes matching the predicate, refreshing only what's needed to match them.
+fn find_processes<F: Fn(&Process) -> bool>(refresh: ProcessRefreshKind, predicate: F) -> Vec<Pid> {
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+    sys.processes()
+        .values()
+        .filter(|process| predicate(process))
+        .map(|process| Pid::from_raw(process.pid().as_u32() as _))
+        .collect()
+}
+
+/// The processes in a cgroup and its descendants.
+fn cgroup_processes(path: &Path) -> std::result::Result<Vec<Pid>, String> {
+    let error = |e: std::io::Error| format!("could not read the cgroup {path:?}: {e}");
+    let mut pids = std::fs::read_to_string(path.join("cgroup.procs"))
+        .map_err(error)?
+        .lines()
+        .filter_map(|pid| pid.trim().parse().ok())
+        .map(Pid::from_raw)
+        .collect::<Vec<_>>();
+
+    for entry in std::fs::read_dir(path).map_err(error)?.flatten() {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            pids.extend(cgroup_processes(&entry.path())?);
+        }
+    }
+
+    Ok(pids)
+}
+
+/// Parse a signal given by its number or name, with or without the `SIG` prefix.
+pub fn parse_signal(s: &str) -> Option<Signal> {
+    if let Some(signal) = s
+        .parse()
+        .ok()
+        .and_then(|signum: i32| Signal::try_from(signum).ok())
+    {
+        return Some(signal);
+    }
+
+    if let Ok(signal) = s.to_uppercase().parse() {
+        return Some(signal);
+    }
+
+    format!("SIG{}", s.to_uppercase()).parse().ok()
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum OnReloadAction {
-    /// No action
-    None,
-
     /// Execute a shell command
     ShellCommand(OsString),
 
-    /// Execute an executable
-    Executable(OsString),
+    /// Execute an executable with the given arguments
+    Executable { path: OsString, args: Vec<OsString> },
 
     /// Signal
     Signal {
         signal: Signal,
         target: OnReloadSignalTarget,
     },
+
+    /// Restart the supervised child
+    Restart {
+        /// The signal asking the child to stop.
+        signal: Signal,
+
+        /// How long to wait for the child to stop, before killing it.
+        grace_period: Duration,
+
+        /// The minimum time between restarts.
+        min_interval: Duration,
+    },
+
+    /// POST the reload as JSON to a URL
+    #[cfg(feature = "reload-http")]
+    Http(HttpTarget),
+
+    /// Reload the systemd unit with the given name over D-Bus
+    #[cfg(feature = "dbus")]
+    SystemdReload(String),
+}
+
+/// An HTTP endpoint notified of reloads, e.g. a `/-/reload` endpoint.
+#[cfg(feature = "reload-http")]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct HttpTarget {
+    pub url: reqwest::Url,
+
+    /// Additional headers sent with the request.
+    pub headers: Vec<(String, String)>,
+
+    /// A file containing a bearer token, read for every request so rotated tokens are picked up.
+    pub token_file: Option<PathBuf>,
+}
+
+/// A reload, as told to hooks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reload {
+    /// Counts the reloads since Contemplate was started, starting at 1.
+    pub sequence: u64,
+
+    /// What triggered the reload, e.g. a data source or a signal.
+    pub trigger: String,
+
+    /// The files changed by the reload.
+    pub files: Vec<UpdatedFile>,
+}
+
+impl Reload {
+    /// The same reload, limited to the given file.
+    pub fn only(&self, path: &Path) -> Self {
+        Self {
+            files: self
+                .files
+                .iter()
+                .filter(|file| file.path == path)
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpdatedFile {
+    pub path: PathBuf,
+
+    /// The SHA-256 of the file before the reload, if it existed.
+    pub old_sha256: Option<String>,
+
+    /// The SHA-256 of the file after the reload.
+    pub new_sha256: Option<String>,
+}
+
+/// The hex-encoded SHA-256 of a file, if it can be read.
+pub fn sha256<P: AsRef<Path>>(path: P) -> Option<String> {
+    let content = std::fs::read(path).ok()?;
+    Some(Sha256::digest(content).encode_hex())
+}
+
+/// A JSON manifest of a reload, removed once dropped.
+struct Manifest {
+    path: PathBuf,
+}
+
+impl Manifest {
+    fn write(reload: &Reload) -> Result<Self> {
+        use std::hash::{BuildHasher, RandomState};
+
+        let contents = serde_json::to_vec_pretty(reload)?;
+        loop {
+            // the temporary directory is shared, so the name must not be predictable, and
+            // an existing file, or a symlink planted in its place, must not be written to.
+            let random = RandomState::new().hash_one(reload.sequence);
+            let path = std::env::temp_dir().join(format!(
+                "contemplate-{}-reload-{random:016x}.json",
+                std::process::id()
+            ));
+            let mut file = match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => Err(e)?,
+            };
+
+            let manifest = Self { path };
+            file.write_all(&contents)?;
+            return Ok(manifest);
+        }
+    }
 }
 
+impl Drop for Manifest {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Write the manifest of a reload, and describe the reload in environment variables of hooks.
+fn hook_env(reload: &Reload) -> Result<(Manifest, Vec<(&'static str, OsString)>)> {
+    let manifest = Manifest::write(reload)?;
+
+    // Replace with native intersperse when [iter_intersperse] is stabilized
+    // [iter_intersperse]: https://github.com/rust-lang/rust/issues/79524
+    let join = |values: Vec<&OsStr>| -> OsString {
+        Itertools::intersperse(values.into_iter(), OsStr::new(",")).collect()
+    };
+    let hashes = |hash: fn(&UpdatedFile) -> &Option<String>| {
+        join(
+            reload
+                .files
+                .iter()
+                .map(|file| OsStr::new(hash(file).as_deref().unwrap_or_default()))
+                .collect(),
+        )
+    };
+
+    let vars = vec![
+        (
+            "CONTEMPLATED_FILES",
+            join(
+                reload
+                    .files
+                    .iter()
+                    .map(|file| file.path.as_os_str())
+                    .collect(),
+            ),
+        ),
+        ("CONTEMPLATED_OLD_SHA256", hashes(|file| &file.old_sha256)),
+        ("CONTEMPLATED_NEW_SHA256", hashes(|file| &file.new_sha256)),
+        ("CONTEMPLATED_MANIFEST", manifest.path.clone().into()),
+        (
+            "CONTEMPLATED_RELOAD_SEQUENCE",
+            reload.sequence.to_string().into(),
+        ),
+        ("CONTEMPLATED_RELOAD_TRIGGER", reload.trigger.clone().into()),
+    ];
+
+    Ok((manifest, vars))
+}
+
+/// How reload hooks are run.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct HookPolicy {
+    /// How long a hook may run, before it's killed and considered failed.
+    pub timeout: Option<Duration>,
+
+    /// How often a failed hook is retried.
+    pub retries: u32,
+
+    /// How long to wait before the first retry, doubling with every further retry.
+    pub backoff: Duration,
+
+    /// Run the following actions even if one failed, rather than stopping.
+    pub keep_going: bool,
+}
+
+/// A running chain of hooks, including their retries.
+struct HookRun {
+    current: Arc<std::sync::Mutex<Step>>,
+    task: JoinHandle<()>,
+}
+
+/// The step of a chain currently running.
+#[derive(Debug, Default)]
+struct Step {
+    /// What the step is reported as.
+    name: String,
+
+    /// The PID of the current attempt, while it's running.
+    pid: Option<Pid>,
+}
+
+/// The actions executed on reload, in order.
 pub struct OnReload {
-    action: OnReloadAction,
-    child: Mutex<Option<Child>>,
+    actions: Vec<OnReloadAction>,
+
+    /// What the hook is reported as, i.e. the command line or the template declaring it.
+    name: String,
+    run: Mutex<Option<HookRun>>,
+    policy: HookPolicy,
+
+    /// The status file to report the outcome of hooks to.
+    status: Option<Arc<StatusFile>>,
+
+    /// The supervisor of the `--and-then-exec` executable, which `:parent` refers to.
+    supervisor: Option<Arc<Supervisor>>,
+
+    /// When the supervised child was last restarted.
+    last_restart: Arc<Mutex<Option<Instant>>>,
 }
 
 impl OnReload {
+    /// How long to wait for a running hook to finish when shutting down.
+    pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Signal the supervised child rather than the parent process.
+    pub fn with_supervisor(mut self, supervisor: Option<Arc<Supervisor>>) -> Self {
+        self.supervisor = supervisor;
+        self
+    }
+
+    /// Time out and retry hooks according to the policy.
+    pub fn with_policy(mut self, policy: HookPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Report the outcome of hooks to the status file.
+    pub fn with_status(mut self, status: Option<Arc<StatusFile>>) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Another action, e.g. declared by a template, sharing the supervisor, policy and status file.
+    pub fn for_action<S: Into<String>>(&self, action: OnReloadAction, name: S) -> Self {
+        let mut on_reload = Self::from(action)
+            .with_supervisor(self.supervisor.clone())
+            .with_policy(self.policy.clone())
+            .with_status(self.status.clone());
+        on_reload.name = name.into();
+        on_reload
+    }
+
+    /// What the action at the given index is reported as, numbering them if there are several.
+    fn step_name(&self, index: usize) -> String {
+        match self.actions.len() {
+            1 => self.name.clone(),
+            _ => format!("{} #{}", self.name, index + 1),
+        }
+    }
+
+    /// Wait for a running hook to finish, killing it if it doesn't within the timeout.
+    ///
     /// Must be called from the context of a tokio runtime.
-    async fn terminate_existing_child(&self) -> Result<()> {
-        let mut child = self.child.lock().await;
-        if let Some(mut child) = child.take()
-            && let Some(pid) = child.id()
-        {
-            kill(Pid::from_raw(pid as _), SIGINT)?;
-            tokio::spawn(async move { child.wait().await });
+    pub async fn shutdown(&self, timeout: Duration) {
+        let Some(mut run) = self.run.lock().await.take() else {
+            return;
+        };
+
+        if !run.task.is_finished() {
+            log::info!("Waiting for the on-reload hook ({}) to finish", self.name);
+        }
+        if tokio::time::timeout(timeout, &mut run.task).await.is_err() {
+            log::warn!(
+                "On-reload hook ({}) did not finish within {timeout:?}, killing it",
+                self.name
+            );
+            if let Some(pid) = run.current.lock().expect("lock poisoned").pid
+                && let Err(e) = kill(pid, SIGKILL)
+            {
+                log::warn!("Could not kill the on-reload hook ({}): {e}", self.name);
+            }
+            run.task.abort();
+        }
+    }
+
+    /// Interrupt a running hook, and cancel its retries and the remaining steps.
+    fn cancel(&self, run: HookRun) -> Result<()> {
+        if run.task.is_finished() {
+            return Ok(());
         }
 
+        let step = std::mem::take(&mut *run.current.lock().expect("lock poisoned"));
+        if let Some(pid) = step.pid {
+            kill(pid, SIGINT)?;
+        }
+        run.task.abort();
+        report(
+            &self.status,
+            &step.name,
+            HookStatus::new(HookState::Cancelled, 0),
+        );
         Ok(())
     }
 
+    /// Run the actions in the background, replacing the running ones.
+    ///
     /// Must be called from the context of a tokio runtime.
-    pub async fn execute<'a, F, P>(&self, updated_files: F) -> Result<()>
-    where
-        F: Iterator<Item = P>,
-        P: Into<Cow<'a, Path>>,
-    {
-        // Replace with native intersperse when [iter_intersperse] is stabilized
-        // [iter_intersperse]: https://github.com/rust-lang/rust/issues/79524
-        let contemplated_files: OsString = Itertools::intersperse(
-            updated_files.map(|path| path.into().as_os_str().to_owned()),
-            OsString::from(","),
-        )
-        .collect();
-
-        match self.action {
-            OnReloadAction::None => {}
-            OnReloadAction::ShellCommand(ref cmd) => {
-                let mut command = Command::new("/bin/sh");
-                command
-                    .arg("-c")
-                    .arg(cmd)
-                    .env("CONTEMPLATED_FILES", contemplated_files);
-                self.terminate_existing_child().await?;
-                let child = command.spawn()?;
-                *self.child.lock().await = Some(child);
+    pub async fn execute(&self, reload: &Reload) -> Result<()> {
+        if self.actions.is_empty() {
+            return Ok(());
+        }
+
+        let mut run = self.run.lock().await;
+        if let Some(run) = run.take() {
+            self.cancel(run)?;
+        }
+
+        let commands = self.actions.iter().any(|action| {
+            matches!(
+                action,
+                OnReloadAction::ShellCommand(_) | OnReloadAction::Executable { .. }
+            )
+        });
+        let (manifest, vars) = match commands {
+            true => hook_env(reload).map(|(manifest, vars)| (Some(manifest), vars))?,
+            false => (None, vec![]),
+        };
+
+        let mut steps = vec![];
+        for (index, action) in self.actions.iter().enumerate() {
+            let task = match action {
+                OnReloadAction::ShellCommand(cmd) => {
+                    let (cmd, vars) = (cmd.clone(), vars.clone());
+                    HookTask::Command(Box::new(move || {
+                        let mut command = Command::new("/bin/sh");
+                        command.arg("-c").arg(&cmd).envs(vars.clone());
+                        command
+                    }))
+                }
+                OnReloadAction::Executable { path, args } => {
+                    let (path, args, vars) = (path.clone(), args.clone(), vars.clone());
+                    HookTask::Command(Box::new(move || {
+                        let mut command = Command::new(&path);
+                        command.args(&args).envs(vars.clone());
+                        command
+                    }))
+                }
+                OnReloadAction::Signal { signal, target } => HookTask::Signal {
+                    signal: *signal,
+                    target: target.clone(),
+                },
+                OnReloadAction::Restart {
+                    signal,
+                    grace_period,
+                    min_interval,
+                } => HookTask::Restart {
+                    signal: *signal,
+                    grace_period: *grace_period,
+                    min_interval: *min_interval,
+                },
+                #[cfg(feature = "reload-http")]
+                OnReloadAction::Http(target) => HookTask::Http {
+                    target: target.clone(),
+                    // serialized once, so retries send the same notification.
+                    body: serde_json::to_vec(&Notification {
+                        reload,
+                        timestamp: crate::status::now(),
+                    })?,
+                },
+                #[cfg(feature = "dbus")]
+                OnReloadAction::SystemdReload(unit) => HookTask::SystemdReload(unit.clone()),
+            };
+            steps.push((self.step_name(index), task));
+        }
+
+        let current = Arc::new(std::sync::Mutex::new(Step::default()));
+        let hook = Hook {
+            policy: self.policy.clone(),
+            status: self.status.clone(),
+            supervisor: self.supervisor.clone(),
+            last_restart: self.last_restart.clone(),
+            current: current.clone(),
+            _manifest: manifest,
+        };
+        let task = tokio::spawn(async move { hook.run(steps).await });
+        *run = Some(HookRun { current, task });
+
+        Ok(())
+    }
+}
+
+impl From<Vec<OnReloadAction>> for OnReload {
+    fn from(actions: Vec<OnReloadAction>) -> Self {
+        Self {
+            actions,
+            name: "command line".to_owned(),
+            run: Mutex::new(None),
+            policy: HookPolicy::default(),
+            status: None,
+            supervisor: None,
+            last_restart: Default::default(),
+        }
+    }
+}
+
+impl From<OnReloadAction> for OnReload {
+    fn from(action: OnReloadAction) -> Self {
+        Self::from(vec![action])
+    }
+}
+
+/// Report the outcome of a hook to the status file, if any.
+fn report(status: &Option<Arc<StatusFile>>, name: &str, hook: HookStatus) {
+    if let Some(status) = status {
+        status.update(|status| {
+            status.hooks.insert(name.to_owned(), hook);
+        });
+    }
+}
+
+/// The JSON body POSTed to HTTP hooks.
+#[cfg(feature = "reload-http")]
+#[derive(Serialize)]
+struct Notification<'a> {
+    #[serde(flatten)]
+    reload: &'a Reload,
+
+    /// When the hook was notified, formatted as RFC 3339.
+    timestamp: String,
+}
+
+/// What a step of a hook runs on every attempt.
+enum HookTask {
+    Command(Box<dyn Fn() -> Command + Send + Sync>),
+
+    Signal {
+        signal: Signal,
+        target: OnReloadSignalTarget,
+    },
+
+    Restart {
+        signal: Signal,
+        grace_period: Duration,
+        min_interval: Duration,
+    },
+
+    #[cfg(feature = "reload-http")]
+    Http {
+        target: HttpTarget,
+        body: Vec<u8>,
+    },
+
+    #[cfg(feature = "dbus")]
+    SystemdReload(String),
+}
+
+/// The outcome of a single attempt of a hook.
+struct Outcome {
+    exit_code: Option<i32>,
+
+    /// Why the attempt failed, if it did.
+    error: Option<String>,
+}
+
+impl From<std::result::Result<ExitStatus, String>> for Outcome {
+    fn from(status: std::result::Result<ExitStatus, String>) -> Self {
+        match status {
+            Ok(status) => Self {
+                exit_code: status.code(),
+                error: (!status.success()).then(|| format!("exited with {status}")),
+            },
+            Err(error) => Self {
+                exit_code: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+impl From<std::result::Result<(), String>> for Outcome {
+    fn from(result: std::result::Result<(), String>) -> Self {
+        Self {
+            exit_code: None,
+            error: result.err(),
+        }
+    }
+}
+
+/// A chain of hooks run in the background.
+struct Hook {
+    policy: HookPolicy,
+    status: Option<Arc<StatusFile>>,
+    supervisor: Option<Arc<Supervisor>>,
+    last_restart: Arc<Mutex<Option<Instant>>>,
+    current: Arc<std::sync::Mutex<Step>>,
+
+    /// Kept until the hook finished or was cancelled, including its retries.
+    _manifest: Option<Manifest>,
+}
+
+impl Hook {
+    /// Run the steps in order, stopping at the first failing one unless told to keep going.
+    async fn run(self, steps: Vec<(String, HookTask)>) {
+        let count = steps.len();
+        for (index, (name, task)) in steps.into_iter().enumerate() {
+            self.current.lock().expect("lock poisoned").name = name.clone();
+            if !self.run_step(&name, &task).await && !self.policy.keep_going && index + 1 < count {
+                log::warn!("Skipping the on-reload hooks following ({name})");
+                return;
             }
-            OnReloadAction::Executable(ref executable) => {
-                let mut command = Command::new(executable);
-                command.env("CONTEMPLATED_FILES", contemplated_files);
-                self.terminate_existing_child().await?;
-                let child = command.spawn()?;
-                *self.child.lock().await = Some(child);
+        }
+    }
+
+    /// Run a step until it succeeds or runs out of retries, and report whether it succeeded.
+    async fn run_step(&self, name: &str, task: &HookTask) -> bool {
+        let attempts = self.policy.retries + 1;
+        let mut backoff = self.policy.backoff;
+
+        for attempt in 1..=attempts {
+            report(
+                &self.status,
+                name,
+                HookStatus::new(HookState::Running, attempt),
+            );
+            let outcome = self.attempt(task).await;
+            let mut hook = HookStatus::new(HookState::Failed, attempt);
+            hook.exit_code = outcome.exit_code;
+
+            let Some(error) = outcome.error else {
+                log::info!("On-reload hook ({name}) succeeded");
+                hook.state = HookState::Succeeded;
+                report(&self.status, name, hook);
+                return true;
+            };
+            hook.error = Some(error.clone());
+
+            if attempt < attempts {
+                log::warn!(
+                    "On-reload hook ({name}) failed: {error}. Retrying in {backoff:?} (attempt {attempt} of {attempts})"
+                );
+                report(&self.status, name, hook);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            } else {
+                log::error!("On-reload hook ({name}) failed: {error}");
+                report(&self.status, name, hook);
             }
-            OnReloadAction::Signal {
-                ref signal,
-                ref target,
-            } => {
-                match target {
-                    OnReloadSignalTarget::Pid(pid) => {
-                        log::debug!("Sending signal {signal} to PID {pid}");
-                        kill(*pid, *signal)?;
-                    }
-                    OnReloadSignalTarget::Parent => {
-                        let pid = Pid::parent();
-                        log::debug!("Sending signal {signal} to parent PID {pid}");
-                        kill(pid, *signal)?;
-                    }
-                    OnReloadSignalTarget::ProcessName(name) => {
-                        let sys = System::new_all();
-
-                        for process in sys.processes_by_name(name) {
-                            log::debug!(
-                                "Sending signal {signal} to {:?} (PID {})",
-                                process.name(),
-                                process.pid()
-                            );
-                            let pid = Pid::from_raw(process.pid().as_u32() as _);
-                            kill(pid, *signal)?;
-                        }
+        }
+
+        false
+    }
+
+    /// Run the task once.
+    async fn attempt(&self, task: &HookTask) -> Outcome {
+        match task {
+            HookTask::Command(command) => self.run_command(command()).await.into(),
+            HookTask::Signal { signal, target } => self.signal(*signal, target).into(),
+            HookTask::Restart {
+                signal,
+                grace_period,
+                min_interval,
+            } => self
+                .restart(*signal, *grace_period, *min_interval)
+                .await
+                .into(),
+            #[cfg(feature = "reload-http")]
+            HookTask::Http { target, body } => {
+                self.with_timeout(post(target, body.clone())).await.into()
+            }
+            #[cfg(feature = "dbus")]
+            HookTask::SystemdReload(unit) => self
+                .with_timeout(crate::systemd::reload_unit(unit))
+                .await
+                .into(),
+        }
+    }
+
+    /// Fail the future if it doesn't finish within the timeout of the policy.
+    #[cfg(any(feature = "reload-http", feature = "dbus"))]
+    async fn with_timeout<F>(&self, future: F) -> std::result::Result<(), String>
+    where
+        F: Future<Output = std::result::Result<(), String>>,
+    {
+        match self.policy.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, future)
+                .await
+                .unwrap_or_else(|_| Err(format!("timed out after {timeout:?}"))),
+            None => future.await,
+        }
+    }
+
+    /// Run the command once, killing it if it times out.
+    async fn run_command(&self, mut command: Command) -> std::result::Result<ExitStatus, String> {
+        let set_pid = |pid| self.current.lock().expect("lock poisoned").pid = pid;
+        let mut child = crate::supervise::spawn(&mut command)
+            .map_err(|e| format!("could not be started: {e}"))?;
+        set_pid(child.id().map(|pid| Pid::from_raw(pid as _)));
+
+        let status = match self.policy.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, child.wait()).await {
+                Ok(status) => status,
+                Err(_) => {
+                    let _ = child.kill().await;
+                    set_pid(None);
+                    return Err(format!("timed out after {timeout:?}"));
+                }
+            },
+            None => child.wait().await,
+        };
+        set_pid(None);
+
+        status.map_err(|e| format!("could not be waited for: {e}"))
+    }
+
+    fn signal(
+        &self,
+        signal: Signal,
+        target: &OnReloadSignalTarget,
+    ) -> std::result::Result<(), String> {
+        let send = |pid: Pid| {
+            kill(pid, signal).map_err(|e| format!("could not send {signal} to PID {pid}: {e}"))
+        };
+
+        match target {
+            OnReloadSignalTarget::Parent => match self.supervisor {
+                Some(ref supervisor) => {
+                    if let Some(pid) = supervisor.pid() {
+                        log::debug!("Sending signal {signal} to supervised PID {pid}");
+                        send(pid)?;
                     }
+                }
+                None => {
+                    let pid = Pid::parent();
+                    log::debug!("Sending signal {signal} to parent PID {pid}");
+                    send(pid)?;
+                }
+            },
+            OnReloadSignalTarget::ParentGroup => {
+                let Some(pid) = self.supervisor.as_ref().and_then(|s| s.pid()) else {
+                    return Err("no supervised child is running".into());
                 };
+                let group = getpgid(Some(pid))
+                    .map_err(|e| format!("could not get the process group of PID {pid}: {e}"))?;
+                // signaling a shared group would signal contemplate itself.
+                if group == getpgrp() {
+                    return Err("the supervised child has no process group of its own".into());
+                }
+                log::debug!("Sending signal {signal} to process group {group}");
+                killpg(group, signal).map_err(|e| {
+                    format!("could not send {signal} to process group {group}: {e}")
+                })?;
             }
-        }
+            target => {
+                for pid in target.processes()? {
+                    log::debug!("Sending signal {signal} to PID {pid} ({target:?})");
+                    send(pid)?;
+                }
+            }
+        };
 
         Ok(())
     }
+
+    /// Restart the supervised child, at most once within the minimum interval.
+    async fn restart(
+        &self,
+        signal: Signal,
+        grace_period: Duration,
+        min_interval: Duration,
+    ) -> std::result::Result<(), String> {
+        let Some(ref supervisor) = self.supervisor else {
+            log::warn!("Not restarting, since no child is supervised");
+            return Ok(());
+        };
+
+        // a delayed restart is cancelled by the next reload, so changes in the meantime
+        // are applied in a single restart.
+        let mut last_restart = self.last_restart.clone().lock_owned().await;
+        if let Some(wait) = last_restart.and_then(|last| min_interval.checked_sub(last.elapsed())) {
+            log::info!("Delaying the restart by {wait:?} to rate-limit restarts");
+            tokio::time::sleep(wait).await;
+        }
+
+        // restarted in a task of its own, so cancelling the hook doesn't leave the child stopped.
+        let supervisor = supervisor.clone();
+        tokio::spawn(async move {
+            *last_restart = Some(Instant::now());
+            supervisor.restart(signal, grace_period).await
+        })
+        .await
+        .map_err(|e| format!("could not restart: {e}"))?
+        .map_err(|e| format!("could not restart: {e}"))
+    }
 }
 
-impl From<OnReloadAction> for OnReload {
-    fn from(action: OnReloadAction) -> Self {
-        Self {
-            action,
-            child: Mutex::new(None),
+/// POST the body to the target, failing unless it responds with a success status.
+#[cfg(feature = "reload-http")]
+async fn post(target: &HttpTarget, body: Vec<u8>) -> std::result::Result<(), String> {
+    static CLIENT: std::sync::LazyLock<reqwest::Client> = std::sync::LazyLock::new(|| {
+        reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .read_timeout(Duration::from_secs(30))
+            .build()
+            .expect("default client must build")
+    });
+
+    let mut request = CLIENT
+        .post(target.url.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    for (name, value) in &target.headers {
+        request = request.header(name, value);
+    }
+    if let Some(ref path) = target.token_file {
+        let token = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read the token from {path:?}: {e}"))?;
+        request = request.bearer_auth(token.trim());
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("could not be sent: {e}"))?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(format!("responded with {status}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hook_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.json");
+        let status = Arc::new(StatusFile::new(&path));
+
+        let policy = HookPolicy {
+            timeout: Some(Duration::from_millis(500)),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let hook = |cmd: &str| {
+            OnReload::from(OnReloadAction::ShellCommand(cmd.into()))
+                .with_policy(policy.clone())
+                .with_status(Some(status.clone()))
+        };
+        let reload = |paths: &[&str]| Reload {
+            sequence: 7,
+            trigger: "test".into(),
+            files: paths
+                .iter()
+                .map(|path| UpdatedFile {
+                    path: path.into(),
+                    old_sha256: None,
+                    new_sha256: Some(format!("{path}-hash")),
+                })
+                .collect(),
+        };
+        let read_status = || -> serde_json::Value {
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap()
+        };
+
+        let failing = hook("exit 3");
+        failing.execute(&reload(&["a"])).await.unwrap();
+        failing.shutdown(Duration::from_secs(5)).await;
+        let hook_status = &read_status()["hooks"]["command line"];
+        assert_eq!(hook_status["state"], "failed");
+        assert_eq!(hook_status["attempts"], 3);
+        assert_eq!(hook_status["exit_code"], 3);
+
+        let slow = hook("sleep 5");
+        slow.execute(&reload(&["a"])).await.unwrap();
+        slow.shutdown(Duration::from_secs(5)).await;
+        let hook_status = &read_status()["hooks"]["command line"];
+        assert_eq!(hook_status["state"], "failed");
+        assert_eq!(hook_status["error"], "timed out after 500ms");
+
+        let succeeding = hook(indoc::indoc! {r#"
+            test "$CONTEMPLATED_FILES" = a,b &&
+            test "$CONTEMPLATED_OLD_SHA256" = , &&
+            test "$CONTEMPLATED_NEW_SHA256" = a-hash,b-hash &&
+            test "$CONTEMPLATED_RELOAD_SEQUENCE" = 7 &&
+            test "$CONTEMPLATED_RELOAD_TRIGGER" = test &&
+            grep -q '"path": "b"' "$CONTEMPLATED_MANIFEST"
+        "#});
+        succeeding.execute(&reload(&["a", "b"])).await.unwrap();
+        succeeding.shutdown(Duration::from_secs(5)).await;
+        let hook_status = &read_status()["hooks"]["command line"];
+        assert_eq!(hook_status["state"], "succeeded");
+        assert_eq!(hook_status["attempts"], 1);
+    }
+
+    #[test]
+    fn test_pidfile_invalid_pid() {
+        let pidfile = tempfile::NamedTempFile::new().unwrap();
+        let target = OnReloadSignalTarget::PidFile(pidfile.path().into());
+        for content in ["-1\n", "0\n", "nginx\n"] {
+            std::fs::write(&pidfile, content).unwrap();
+            assert!(target.processes().is_err(), "{content:?} was accepted");
         }
     }
+
+    #[tokio::test]
+    async fn test_signal_targets() {
+        let target = |s: &str| OnReloadSignalTarget::try_from(OsStr::new(s));
+        assert_eq!(
+            target(":parent-group"),
+            Ok(OnReloadSignalTarget::ParentGroup)
+        );
+        assert_eq!(
+            target("pidfile:/run/nginx.pid"),
+            Ok(OnReloadSignalTarget::PidFile("/run/nginx.pid".into()))
+        );
+        assert_eq!(
+            target("exe:nginx"),
+            Ok(OnReloadSignalTarget::Executable("nginx".into()))
+        );
+        assert!(target("cmdline:(").is_err());
+        assert!(target("0").is_err());
+        assert!(target("-1").is_err());
+
+        let pidfile = tempfile::NamedTempFile::new().unwrap();
+        let mut by_pidfile = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&pidfile, format!("{}\n", by_pidfile.id())).unwrap();
+        let mut by_cmdline = std::process::Command::new("sleep")
+            .arg("31.5")
+            .spawn()
+            .unwrap();
+
+        let on_reload = OnReload::from(vec![
+            OnReloadAction::Signal {
+                signal: Signal::SIGTERM,
+                target: target(&format!("pidfile:{}", pidfile.path().display())).unwrap(),
+            },
+            OnReloadAction::Signal {
+                signal: Signal::SIGTERM,
+                target: target(r"cmdline:^sleep 31\.5$").unwrap(),
+            },
+        ]);
+        let reload = Reload {
+            sequence: 1,
+            trigger: "test".into(),
+            files: vec![],
+        };
+        on_reload.execute(&reload).await.unwrap();
+        on_reload.shutdown(Duration::from_secs(5)).await;
+
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(
+            by_pidfile.wait().unwrap().signal(),
+            Some(Signal::SIGTERM as _)
+        );
+        assert_eq!(
+            by_cmdline.wait().unwrap().signal(),
+            Some(Signal::SIGTERM as _)
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let reload = Reload {
+            sequence: 7,
+            trigger: "test".into(),
+            files: vec![],
+        };
+        let (first, second) = (
+            Manifest::write(&reload).unwrap(),
+            Manifest::write(&reload).unwrap(),
+        );
+        assert_ne!(first.path, second.path);
+
+        let metadata = std::fs::symlink_metadata(&first.path).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&first.path).unwrap()).unwrap();
+        assert_eq!(written["sequence"], 7);
+
+        let path = first.path.clone();
+        std::mem::drop(first);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = Arc::new(StatusFile::new(dir.path().join("status.json")));
+        let marker = dir.path().join("marker");
+
+        let chain = |keep_going| {
+            OnReload::from(vec![
+                OnReloadAction::ShellCommand("exit 1".into()),
+                OnReloadAction::ShellCommand(format!("touch {}", marker.display()).into()),
+            ])
+            .with_policy(HookPolicy {
+                keep_going,
+                ..Default::default()
+            })
+            .with_status(Some(status.clone()))
+        };
+        let reload = Reload {
+            sequence: 1,
+            trigger: "test".into(),
+            files: vec![],
+        };
+
+        let stopping = chain(false);
+        stopping.execute(&reload).await.unwrap();
+        stopping.shutdown(Duration::from_secs(5)).await;
+        assert!(!marker.exists());
+
+        let continuing = chain(true);
+        continuing.execute(&reload).await.unwrap();
+        continuing.shutdown(Duration::from_secs(5)).await;
+        assert!(marker.exists());
+
+        let status: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("status.json")).unwrap())
+                .unwrap();
+        assert_eq!(status["hooks"]["command line #1"]["state"], "failed");
+        assert_eq!(status["hooks"]["command line #2"]["state"], "succeeded");
+    }
+
+    #[cfg(feature = "reload-http")]
+    #[tokio::test]
+    async fn test_http_hook() {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/-/reload", listener.local_addr().unwrap());
+
+        // fails the first request, and returns the body of the second one.
+        let server = std::thread::spawn(move || {
+            let mut body = vec![];
+            for response in ["503 Service Unavailable", "200 OK"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    &stream,
+                    "HTTP/1.1 {response}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+            body
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.json");
+        let status = Arc::new(StatusFile::new(&path));
+        let hook = OnReload::from(OnReloadAction::Http(HttpTarget {
+            url: url.parse().unwrap(),
+            headers: vec![],
+            token_file: None,
+        }))
+        .with_policy(HookPolicy {
+            timeout: Some(Duration::from_secs(5)),
+            retries: 1,
+            backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .with_status(Some(status));
+        let reload = Reload {
+            sequence: 2,
+            trigger: "test".into(),
+            files: vec![UpdatedFile {
+                path: "a".into(),
+                old_sha256: None,
+                new_sha256: Some("a-hash".into()),
+            }],
+        };
+
+        hook.execute(&reload).await.unwrap();
+        hook.shutdown(Duration::from_secs(5)).await;
+
+        let body: serde_json::Value = serde_json::from_slice(&server.join().unwrap()).unwrap();
+        assert_eq!(body["sequence"], 2);
+        assert_eq!(body["files"][0]["new_sha256"], "a-hash");
+        assert!(body["timestamp"].is_string());
+
+        let status: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(status["hooks"]["command line"]["state"], "succeeded");
+        assert_eq!(status["hooks"]["command line"]["attempts"], 2);
+    }
 }
@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use jsonschema::Validator;
+
+use crate::error::{Error, Result};
+
+/// A JSON schema the merged context is validated against.
+#[derive(Debug)]
+pub struct Schema {
+    validator: Validator,
+}
+
+impl Schema {
+    /// Load and compile a JSON schema from the given path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let schema: serde_json::Value = std::fs::File::open(path)
+            .map_err(Error::from)
+            .and_then(|f| Ok(serde_json::from_reader(f)?))
+            .map_err(|e| Error::InvalidSchema(format!("{path:?}: {e}")))?;
+        Self::new(&schema)
+    }
+
+    pub fn new(schema: &serde_json::Value) -> Result<Self> {
+        let validator =
+            jsonschema::validator_for(schema).map_err(|e| Error::InvalidSchema(e.to_string()))?;
+        Ok(Self { validator })
+    }
+
+    /// Validate the context against the schema, returning all violations.
+    pub fn validate(&self, ctx: &serde_json::Value) -> Result<()> {
+        let violations: Vec<String> = self
+            .validator
+            .iter_errors(ctx)
+            .map(|e| match e.instance_path().as_str() {
+                "" => format!("/: {e}"),
+                path => format!("{path}: {e}"),
+            })
+            .collect();
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::SchemaViolation(violations))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate() {
+        let schema = Schema::new(&json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "port": { "type": "integer" }
+            }
+        }))
+        .unwrap();
+
+        assert!(schema.validate(&json!({ "name": "x", "port": 80 })).is_ok());
+
+        let Err(Error::SchemaViolation(violations)) = schema.validate(&json!({ "port": "80" }))
+        else {
+            panic!("expected schema violations");
+        };
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().any(|v| v.starts_with("/port: ")));
+        assert!(violations.iter().any(|v| v.starts_with("/: ")));
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        assert!(matches!(
+            Schema::new(&json!({ "type": "no-such-type" })),
+            Err(Error::InvalidSchema(_))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! The outcome of renders and reload hooks in watch mode, written as JSON for health checks.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::Result;
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Status {
+    /// When the templates were last rendered.
+    pub rendered_at: Option<String>,
+
+    /// The files changed by the last render.
+    pub updated_files: Vec<PathBuf>,
+
+    /// The error preventing the last render, if any.
+    pub error: Option<String>,
+
+    /// The outcome of the reload hooks, by the command line or the template declaring them.
+    pub hooks: BTreeMap<String, HookStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookState {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HookStatus {
+    pub state: HookState,
+
+    /// The attempts made, including retries.
+    pub attempts: u32,
+
+    /// The exit code of the last attempt, if it exited.
+    pub exit_code: Option<i32>,
+
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+
+    pub updated_at: String,
+}
+
+impl HookStatus {
+    pub fn new(state: HookState, attempts: u32) -> Self {
+        Self {
+            state,
+            attempts,
+            exit_code: None,
+            error: None,
+            updated_at: now(),
+        }
+    }
+}
+
+/// The current time, formatted as RFC 3339.
+pub fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// A [Status], written to a file whenever it's updated.
+#[derive(Debug)]
+pub struct StatusFile {
+    path: PathBuf,
+    status: Mutex<Status>,
+}
+
+impl StatusFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            status: Default::default(),
+        }
+    }
+
+    /// Update the status, and write it to the file.
+    pub fn update<F: FnOnce(&mut Status)>(&self, f: F) {
+        let mut status = self.status.lock().expect("lock poisoned");
+        f(&mut status);
+        if let Err(e) = self.write(&status) {
+            log::warn!("Could not write the status to {:?}: {e}", self.path);
+        }
+    }
+
+    /// Write the status to a temporary file first, so readers never see a partial status.
+    fn write(&self, status: &Status) -> Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(status)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
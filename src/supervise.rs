@@ -0,0 +1,403 @@
+//! Supervising the `--and-then-exec` executable as a child, rather than executing it.
+//!
+//! The supervisor stays in the foreground (e.g. as PID 1 of a container), forwards signals to
+//! its child, reaps orphaned processes re-parented to it, and exits with the exit code of its child.
+
+use std::collections::HashSet;
+use std::ffi::{CString, OsString};
+use std::os::fd::AsFd;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::process::CommandExt;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::error::Result;
+use nix::sys::signal::{SigHandler, SigSet, SigmaskHow, Signal, kill, signal as set_handler};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::{Pid, getpgrp, tcgetpgrp, tcsetpgrp};
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
+
+/// Processes spawned through [spawn], whose exit status is collected by tokio.
+static SPAWNED: LazyLock<Mutex<HashSet<Pid>>> = LazyLock::new(Default::default);
+
+/// Spawn a command, keeping the [Supervisor] from reaping it.
+///
+/// Processes contemplate waits for itself must be spawned this way, or their exit status
+/// may be lost to the supervisor.
+pub fn spawn(command: &mut Command) -> std::io::Result<Child> {
+    let mut spawned = SPAWNED.lock().unwrap();
+    let child = command.spawn()?;
+    spawned.extend(child.id().map(|pid| Pid::from_raw(pid as _)));
+    Ok(child)
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The running child.
+    child: Option<Pid>,
+
+    /// A child being stopped for a restart.
+    stopping: Option<Pid>,
+
+    /// Signals received while the child wasn't started yet, or restarting.
+    pending: Vec<Signal>,
+
+    /// The exit code of the child, once it exited on its own.
+    exit_code: Option<i32>,
+}
+
+#[derive(Debug)]
+pub struct Supervisor {
+    path: OsString,
+    args: Vec<OsString>,
+
+    /// Whether the child is started in a process group of its own.
+    process_group: bool,
+    state: watch::Sender<State>,
+
+    /// Whether orphaned processes are re-parented to contemplate, as PID 1 or a subreaper.
+    reaper: bool,
+}
+
+impl Supervisor {
+    /// Signals forwarded to the child.
+    pub const FORWARDED_SIGNALS: &[Signal] = &[
+        Signal::SIGTERM,
+        Signal::SIGINT,
+        Signal::SIGQUIT,
+        Signal::SIGHUP,
+        Signal::SIGUSR1,
+        Signal::SIGUSR2,
+        Signal::SIGWINCH,
+    ];
+
+    pub fn new(path: CString, args: Vec<CString>) -> Self {
+        Self {
+            path: OsString::from_vec(path.into_bytes()),
+            args: args
+                .into_iter()
+                .map(|arg| OsString::from_vec(arg.into_bytes()))
+                .collect(),
+            process_group: false,
+            state: watch::Sender::new(State::default()),
+            reaper: Pid::this() == Pid::from_raw(1)
+                || nix::sys::prctl::get_child_subreaper().unwrap_or_default(),
+        }
+    }
+
+    /// Start the child in a process group of its own, so the group can be signaled.
+    ///
+    /// The child is always started in a process group of its own when reaping orphans.
+    pub fn with_process_group(mut self, process_group: bool) -> Self {
+        self.process_group = process_group;
+        self
+    }
+
+    /// The PID of the running child, if any.
+    pub fn pid(&self) -> Option<Pid> {
+        self.state.borrow().child
+    }
+
+    /// Start the child.
+    pub fn start(&self) -> Result<()> {
+        // keep the reaper from collecting a child exiting right away, before it's known.
+        let _spawned = SPAWNED.lock().unwrap();
+
+        let mut command = std::process::Command::new(&self.path);
+        if let Some((arg0, args)) = self.args.split_first() {
+            command.arg0(arg0).args(args);
+        }
+        // orphans are told apart from the processes contemplate spawned by their process group.
+        if self.process_group || self.reaper {
+            command.process_group(0);
+            let group = getpgrp();
+            // SAFETY: only async-signal-safe functions are called between fork and exec.
+            unsafe {
+                command.pre_exec(move || {
+                    // like a shell, hand the terminal to the group of the child, if it had
+                    // contemplate's, ignoring the signal stopping background groups meanwhile.
+                    let tty = std::os::fd::BorrowedFd::borrow_raw(0);
+                    if tcgetpgrp(tty) == Ok(group) {
+                        let _ = set_handler(Signal::SIGTTOU, SigHandler::SigIgn);
+                        let _ = tcsetpgrp(tty, getpgrp());
+                        let _ = set_handler(Signal::SIGTTOU, SigHandler::SigDfl);
+                    }
+                    Ok(())
+                });
+            }
+        }
+        let child = command.spawn()?;
+        let pid = Pid::from_raw(child.id() as _);
+        log::info!("Started {:?} as PID {pid}", self.path);
+
+        let mut pending = vec![];
+        self.state.send_modify(|state| {
+            state.child = Some(pid);
+            pending = std::mem::take(&mut state.pending);
+        });
+        for sig in pending {
+            self.forward(sig);
+        }
+        Ok(())
+    }
+
+    /// Stop the child using the given signal, killing it if it doesn't exit within the grace
+    /// period, and start it again with the same arguments.
+    ///
+    /// Must be called from the context of a tokio runtime.
+    pub async fn restart(&self, signal: Signal, grace_period: Duration) -> Result<()> {
+        let mut pid = None;
+        self.state.send_if_modified(|state| {
+            pid = state.child.take();
+            state.stopping = pid;
+            pid.is_some()
+        });
+        // the child exited on its own, and is not restarted.
+        let Some(pid) = pid else {
+            return Ok(());
+        };
+
+        log::info!("Restarting PID {pid}, sending {signal}");
+        let mut state = self.state.subscribe();
+        kill(pid, signal)?;
+        let stopped = state.wait_for(|state| state.stopping != Some(pid));
+        if tokio::time::timeout(grace_period, stopped).await.is_err() {
+            log::warn!("PID {pid} did not exit within {grace_period:?}, killing it");
+            kill(pid, Signal::SIGKILL)?;
+            let _ = state.wait_for(|state| state.stopping != Some(pid)).await;
+        }
+
+        // without a child to supervise, shut down rather than running on.
+        self.start().inspect_err(|_| {
+            self.state.send_modify(|state| state.exit_code = Some(1));
+        })
+    }
+
+    /// Wait for the child to exit on its own, returning its exit code.
+    pub async fn wait(&self) -> i32 {
+        let mut state = self.state.subscribe();
+        let state = state.wait_for(|state| state.exit_code.is_some()).await;
+        state
+            .map(|state| state.exit_code)
+            .ok()
+            .flatten()
+            .unwrap_or(1)
+    }
+
+    /// Forward the given signals to the child and reap exited processes, until the child exits.
+    ///
+    /// Returns the exit code of the child, or 128 plus the signal number if it was killed
+    /// by a signal. Must be called from the context of a tokio runtime.
+    pub async fn supervise(&self, forward: &[Signal]) -> i32 {
+        let mut handlers = vec![];
+        for sig in std::iter::once(Signal::SIGCHLD).chain(forward.iter().copied()) {
+            match signal(SignalKind::from_raw(sig as _)) {
+                Ok(handler) => handlers.push((sig, handler)),
+                Err(e) => log::error!("Could not install a handler for {sig}: {e}"),
+            }
+        }
+
+        loop {
+            self.reap();
+            if let Some(code) = self.state.borrow().exit_code {
+                return code;
+            }
+            if handlers.is_empty() {
+                return self.wait().await;
+            }
+
+            let received = handlers
+                .iter_mut()
+                .map(|(_, handler)| Box::pin(handler.recv()));
+            let (_, index, _) = futures::future::select_all(received).await;
+            let sig = handlers[index].0;
+            if sig == Signal::SIGCHLD {
+                continue;
+            }
+
+            self.forward(sig);
+        }
+    }
+
+    /// Forward a signal to the child, or once it started if it's being (re)started.
+    fn forward(&self, sig: Signal) {
+        let mut pid = None;
+        self.state.send_if_modified(|state| {
+            pid = state.child;
+            if pid.is_none() && state.exit_code.is_none() {
+                state.pending.push(sig);
+            }
+            false
+        });
+
+        if let Some(pid) = pid {
+            log::debug!("Forwarding {sig} to PID {pid}");
+            if let Err(e) = kill(pid, sig) {
+                log::warn!("Could not forward {sig} to PID {pid}: {e}");
+            }
+        }
+    }
+
+    /// Collect the exit status of the child and of orphaned processes.
+    fn reap(&self) {
+        let mut spawned = SPAWNED.lock().unwrap();
+        // forget processes tokio collected already.
+        spawned.retain(|pid| kill(*pid, None).is_ok());
+
+        let state = self.state.borrow();
+        let mut candidates = state
+            .child
+            .into_iter()
+            .chain(state.stopping)
+            .collect::<HashSet<_>>();
+        std::mem::drop(state);
+        if self.reaper {
+            candidates.extend(orphans());
+        }
+
+        for pid in candidates.difference(&spawned) {
+            let code = match waitpid(*pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(_, code)) => code,
+                Ok(WaitStatus::Signaled(_, sig, _)) => 128 + sig as i32,
+                _ => continue,
+            };
+
+            let mut supervised = true;
+            self.state.send_modify(|state| {
+                if state.child == Some(*pid) {
+                    log::info!("PID {pid} exited with code {code}");
+                    state.child = None;
+                    state.exit_code = Some(code);
+                } else if state.stopping == Some(*pid) {
+                    log::info!("PID {pid} stopped with code {code}");
+                    state.stopping = None;
+                } else {
+                    log::debug!("Reaped orphaned PID {pid}");
+                    supervised = false;
+                }
+            });
+
+            // the group of a child is named after it, and is handed the terminal when starting.
+            if supervised && (self.process_group || self.reaper) {
+                reclaim_terminal(*pid);
+            }
+        }
+    }
+}
+
+/// Take the terminal back from the process group of an exited child, so it can be handed
+/// to the child started next.
+fn reclaim_terminal(group: Pid) {
+    let tty = std::io::stdin();
+    if tcgetpgrp(tty.as_fd()) != Ok(group) {
+        return;
+    }
+
+    // contemplate is in a background group until then, which is stopped by changing the
+    // foreground group unless the signal is blocked.
+    let ttou = SigSet::from(Signal::SIGTTOU);
+    let Ok(mask) = ttou.thread_swap_mask(SigmaskHow::SIG_BLOCK) else {
+        return;
+    };
+    match tcsetpgrp(tty.as_fd(), getpgrp()) {
+        Ok(()) => log::debug!("Took the terminal back from process group {group}"),
+        Err(e) => log::warn!("Could not take the terminal back from process group {group}: {e}"),
+    }
+    let _ = mask.thread_set_mask();
+}
+
+/// The exited orphans re-parented to contemplate, as listed in `/proc`.
+///
+/// The processes contemplate spawns share its process group, e.g. those spawned by libraries
+/// waiting for them on their own, and are left alone. The orphans descending from the child
+/// are in the process group of the child, or in a group or session of their own.
+fn orphans() -> Vec<Pid> {
+    let (parent, group) = (Pid::this().to_string(), getpgrp().to_string());
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            // the process name is parenthesized and may contain spaces, so skip past it.
+            let mut fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace();
+            let (state, ppid, pgrp) = (fields.next()?, fields.next()?, fields.next()?);
+            (state == "Z" && ppid == parent && pgrp != group).then(|| Pid::from_raw(pid))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_supervise() {
+        let supervisor = Supervisor::new(
+            CString::new("/bin/sh").unwrap(),
+            ["sh", "-c", "exit 3"]
+                .map(|arg| CString::new(arg).unwrap())
+                .to_vec(),
+        );
+
+        supervisor.start().unwrap();
+        assert_eq!(supervisor.supervise(&[]).await, 3);
+        assert_eq!(supervisor.pid(), None);
+
+        let supervisor = Supervisor::new(
+            CString::new("/bin/sh").unwrap(),
+            ["sh", "-c", "sleep 30"]
+                .map(|arg| CString::new(arg).unwrap())
+                .to_vec(),
+        );
+
+        supervisor.start().unwrap();
+        let first = supervisor.pid().unwrap();
+        let supervise = std::pin::pin!(supervisor.supervise(&[]));
+        let restart = std::pin::pin!(async {
+            supervisor
+                .restart(Signal::SIGTERM, Duration::from_secs(10))
+                .await
+                .unwrap();
+            assert_ne!(supervisor.pid(), Some(first));
+            kill(supervisor.pid().unwrap(), Signal::SIGKILL).unwrap();
+            futures::future::pending::<()>().await;
+        });
+        let code = match futures::future::select(supervise, restart).await {
+            futures::future::Either::Left((code, _)) => code,
+            futures::future::Either::Right(_) => unreachable!(),
+        };
+        assert_eq!(code, 128 + Signal::SIGKILL as i32);
+    }
+
+    #[tokio::test]
+    async fn test_reaper() {
+        let mut supervisor = Supervisor::new(
+            CString::new("/bin/sh").unwrap(),
+            ["sh", "-c", "sleep 1; exit 3"]
+                .map(|arg| CString::new(arg).unwrap())
+                .to_vec(),
+        );
+        // as if running as PID 1, without actually adopting orphans.
+        supervisor.reaper = true;
+
+        supervisor.start().unwrap();
+        let supervise = std::pin::pin!(supervisor.supervise(&[]));
+        let spawn = std::pin::pin!(async {
+            // a process spawned by a library waiting for it on its own, not to be reaped.
+            let mut child = std::process::Command::new("true").spawn().unwrap();
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            assert!(child.wait().unwrap().success());
+            futures::future::pending::<()>().await;
+        });
+        let code = match futures::future::select(supervise, spawn).await {
+            futures::future::Either::Left((code, _)) => code,
+            futures::future::Either::Right(_) => unreachable!(),
+        };
+        assert_eq!(code, 3);
+    }
+}
@@ -0,0 +1,170 @@
+//! Integration with systemd: the service notification protocol, see sd_notify(3), and
+//! reloading units over D-Bus.
+
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+use nix::time::{ClockId, clock_gettime};
+
+/// Notifies systemd of the state of the service, when started as a `Type=notify` service.
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+
+    /// How often systemd expects to be pinged, if the watchdog is enabled.
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// Use the socket systemd passed in `$NOTIFY_SOCKET`, if any.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        // a leading '@' denotes a socket in the abstract namespace.
+        let addr = match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        }
+        .inspect_err(|e| log::warn!("Invalid NOTIFY_SOCKET {path:?}: {e}"))
+        .ok()?;
+        let socket = UnixDatagram::unbound()
+            .inspect_err(|e| log::warn!("Could not create a socket to notify systemd: {e}"))
+            .ok()?;
+
+        // the watchdog is meant for another process, if WATCHDOG_PID doesn't match.
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| {
+                std::env::var("WATCHDOG_PID")
+                    .map_or(true, |pid| pid == std::process::id().to_string())
+            })
+            .and_then(|usec| usec.parse().ok())
+            .filter(|usec| *usec > 0)
+            .map(Duration::from_micros);
+
+        Some(Self {
+            socket,
+            addr,
+            watchdog,
+        })
+    }
+
+    /// Send newline-separated `KEY=VALUE` assignments.
+    fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            log::warn!("Could not notify systemd: {e}");
+        }
+    }
+
+    /// Tell systemd the service is ready, along with a status shown by `systemctl status`.
+    pub fn ready(&self, status: &str) {
+        // a newline would end the assignment.
+        let status = status.replace('\n', " ");
+        self.notify(&format!("READY=1\nSTATUS={status}"));
+    }
+
+    /// Tell systemd the service is reloading, until it's ready again.
+    pub fn reloading(&self) {
+        // lets systemd tell whether the reload is the one it requested.
+        let now = clock_gettime(ClockId::CLOCK_MONOTONIC)
+            .map(|now| now.tv_sec() * 1_000_000 + now.tv_nsec() / 1_000)
+            .unwrap_or_default();
+        self.notify(&format!("RELOADING=1\nMONOTONIC_USEC={now}"));
+    }
+
+    /// Tell systemd the service is shutting down.
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// How often systemd expects the watchdog to be pinged, if it is enabled.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Ping the watchdog, telling systemd the service is still alive.
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+}
+
+/// Reload a unit like `systemctl reload`, waiting for the reload to finish.
+#[cfg(feature = "dbus")]
+pub async fn reload_unit(unit: &str) -> Result<(), String> {
+    use futures::StreamExt;
+    use zbus::zvariant::OwnedObjectPath;
+
+    let error = |e: zbus::Error| format!("could not reload {unit}: {e}");
+    let connection = zbus::Connection::system().await.map_err(error)?;
+    let manager = zbus::Proxy::new(
+        &connection,
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        "org.freedesktop.systemd1.Manager",
+    )
+    .await
+    .map_err(error)?;
+
+    // systemd only announces finished jobs to subscribers, so subscribe before reloading.
+    manager
+        .call::<_, _, ()>("Subscribe", &())
+        .await
+        .map_err(error)?;
+    let mut removed = manager.receive_signal("JobRemoved").await.map_err(error)?;
+    let job: OwnedObjectPath = manager
+        .call("ReloadUnit", &(unit, "replace"))
+        .await
+        .map_err(error)?;
+
+    while let Some(message) = removed.next().await {
+        let (_, path, _, result): (u32, OwnedObjectPath, String, String) =
+            message.body().deserialize().map_err(error)?;
+        if path == job {
+            return match result.as_str() {
+                "done" => Ok(()),
+                result => Err(format!("reloading {unit} finished with result '{result}'")),
+            };
+        }
+    }
+
+    Err(format!("lost the connection to systemd reloading {unit}"))
+}
+
+/// The full name of a unit, which is a service if no type is given, like for `systemctl`.
+#[cfg(feature = "dbus")]
+pub fn unit_name(name: &str) -> String {
+    match name.contains('.') {
+        true => name.to_owned(),
+        false => format!("{name}.service"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_notifier() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier {
+            socket: UnixDatagram::unbound().unwrap(),
+            addr: SocketAddr::from_pathname(&path).unwrap(),
+            watchdog: None,
+        };
+
+        let mut buf = [0; 256];
+        notifier.ready("Rendered\n2 files");
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=Rendered 2 files");
+
+        notifier.reloading();
+        let len = systemd.recv(&mut buf).unwrap();
+        let reloading = OsStr::from_bytes(&buf[..len]).to_string_lossy();
+        assert!(reloading.starts_with("RELOADING=1\nMONOTONIC_USEC="));
+    }
+}
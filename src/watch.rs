@@ -1,9 +1,13 @@
-use std::pin::Pin;
+use std::pin::{Pin, pin};
 
-#[cfg(feature = "webhook")]
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+#[cfg(feature = "webhook")]
+use tokio::sync::oneshot;
 
 use async_trait::async_trait;
+use futures::future::{Either, select};
 use tokio::sync::mpsc;
 
 use crate::datasource::{Notifier, SourceRegistry};
@@ -11,13 +15,21 @@ use crate::datasource::{Notifier, SourceRegistry};
 pub struct WatcherRegistry<'a> {
     pub watchers: Vec<Box<dyn Watch + Sync + Send>>,
     sources: &'a mut SourceRegistry,
-    watch_tx: mpsc::Sender<()>,
-    watch_rx: Option<mpsc::Receiver<()>>,
+    /// Notifications, carrying what triggered them.
+    watch_tx: mpsc::Sender<String>,
+    watch_rx: Option<mpsc::Receiver<String>>,
+    /// Called once all sources and watchers are being watched.
+    ready: Option<Box<dyn FnOnce() + Send + 'a>>,
+    /// Called at the given interval while waiting for changes.
+    heartbeat: Option<(Duration, Box<dyn Fn() + Send + Sync + 'a>)>,
 }
 
 #[async_trait]
 pub trait Watch: std::fmt::Debug + Send {
     async fn watch(&mut self, _notify: Notifier) {}
+
+    /// Stop watching, releasing resources like listening sockets.
+    async fn shutdown(&self) {}
 }
 
 impl<'a> WatcherRegistry<'a> {
@@ -32,19 +44,44 @@ impl<'a> WatcherRegistry<'a> {
             watchers,
             watch_tx,
             watch_rx: Some(watch_rx),
+            ready: None,
+            heartbeat: None,
         }
     }
 
-    /// Watch for changes on the underlying data sources.
+    /// Call `ready` once all sources and watchers are being watched.
+    pub fn with_ready(mut self, ready: impl FnOnce() + Send + 'a) -> Self {
+        self.ready = Some(Box::new(ready));
+        self
+    }
+
+    /// Call `beat` every `interval` while waiting for changes.
+    ///
+    /// No beats happen while a change is handled, so missing beats tell that handling
+    /// a change hangs.
+    pub fn with_heartbeat(
+        mut self,
+        interval: Duration,
+        beat: impl Fn() + Send + Sync + 'a,
+    ) -> Self {
+        self.heartbeat = Some((interval, Box::new(beat)));
+        self
+    }
+
+    /// Watch for changes on the underlying data sources, until `shutdown` completes.
+    ///
+    /// `cb` is called with the sources and what triggered the change.
+    /// A render in progress when `shutdown` completes is finished first.
     ///
     /// # Panics
     /// panics if `watch` is called multiple times on a [SourceRegistry].
     pub async fn watch<
         'b,
-        F: Fn(&'b SourceRegistry) -> Pin<Box<dyn futures::Future<Output = ()> + Send + 'b>>,
+        F: Fn(&'b SourceRegistry, String) -> Pin<Box<dyn futures::Future<Output = ()> + Send + 'b>>,
     >(
         &'b mut self,
         cb: F,
+        shutdown: impl futures::Future<Output = ()>,
     ) {
         let Some(mut watch_rx) = self.watch_rx.take() else {
             panic!("This source registry is already being watched.");
@@ -53,7 +90,7 @@ impl<'a> WatcherRegistry<'a> {
         for source in self.sources.sources.iter_mut() {
             let notifier = Notifier::new(self.watch_tx.clone());
             log::debug!("watching source: {source:?}");
-            source.watch(notifier).await
+            source.source.watch(notifier).await
         }
 
         for watcher in self.watchers.iter_mut() {
@@ -62,17 +99,95 @@ impl<'a> WatcherRegistry<'a> {
             watcher.watch(notifier).await
         }
 
+        if let Some(ready) = self.ready.take() {
+            ready();
+        }
+
         // Downgrade to shared reference here.
         let self_ = &*self;
 
+        let mut ticks = self_.heartbeat.as_ref().map(|(interval, _)| {
+            let mut ticks = tokio::time::interval(*interval);
+            // beat once after handling a change, rather than catching up on the missed beats.
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            ticks
+        });
+        let mut shutdown = pin!(shutdown);
         loop {
-            let Some(()) = watch_rx.recv().await else {
-                log::debug!("All watchers terminated.");
-                break;
+            let tick = async {
+                match ticks.as_mut() {
+                    Some(ticks) => ticks.tick().await,
+                    None => futures::future::pending().await,
+                }
             };
+            let (recv, tick) = (pin!(watch_rx.recv()), pin!(tick));
+            match select(select(recv, tick), shutdown.as_mut()).await {
+                Either::Left((Either::Left((Some(trigger), _)), _)) => {
+                    cb(self_.sources, trigger).await
+                }
+                Either::Left((Either::Left((None, _)), _)) => {
+                    log::debug!("All watchers terminated.");
+                    break;
+                }
+                Either::Left((Either::Right(_), _)) => {
+                    if let Some((_, beat)) = &self_.heartbeat {
+                        beat();
+                    }
+                }
+                Either::Right(_) => break,
+            }
+        }
 
-            cb(self_.sources).await;
+        // stop accepting notifications
+        watch_rx.close();
+        for source in self_.sources.sources.iter() {
+            source.source.shutdown().await;
         }
+        for watcher in self_.watchers.iter() {
+            watcher.shutdown().await;
+        }
+    }
+}
+
+/// Triggers a reload on SIGHUP, and additionally re-reads templates on SIGUSR1.
+#[derive(Debug)]
+pub struct SignalWatcher {
+    /// Set on SIGUSR1, and reset once the templates are re-read.
+    reload_templates: Arc<AtomicBool>,
+}
+
+impl SignalWatcher {
+    pub fn new(reload_templates: Arc<AtomicBool>) -> Self {
+        Self { reload_templates }
+    }
+}
+
+#[async_trait]
+impl Watch for SignalWatcher {
+    async fn watch(&mut self, notify: Notifier) {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let (Ok(mut hangup), Ok(mut user1)) = (
+            signal(SignalKind::hangup()),
+            signal(SignalKind::user_defined1()),
+        ) else {
+            log::error!("Could not install the SIGHUP and SIGUSR1 handlers");
+            return;
+        };
+
+        let reload_templates = self.reload_templates.clone();
+        tokio::spawn(async move {
+            loop {
+                match select(pin!(hangup.recv()), pin!(user1.recv())).await {
+                    Either::Left((Some(()), _)) => notify.notify_async(&"SIGHUP").await,
+                    Either::Right((Some(()), _)) => {
+                        reload_templates.store(true, Ordering::SeqCst);
+                        notify.notify_async(&"SIGUSR1").await
+                    }
+                    _ => break,
+                }
+            }
+        });
     }
 }
 
@@ -106,15 +221,20 @@ impl Watch for PollWatcher {
 }
 
 #[cfg(feature = "webhook")]
-#[derive(Debug)]
 pub struct WebHook {
     listen: String,
+
+    /// Stops the server, which completes the task once pending requests are answered.
+    server: std::sync::Mutex<Option<(oneshot::Sender<()>, tokio::task::JoinHandle<()>)>>,
 }
 
 #[cfg(feature = "webhook")]
 impl WebHook {
     pub fn new(listen: String) -> Self {
-        Self { listen }
+        Self {
+            listen,
+            server: Default::default(),
+        }
     }
 }
 
@@ -140,6 +260,100 @@ impl Watch for WebHook {
                 ),
             )
             .with_state((Arc::new(notify), self_dbg));
-        tokio::spawn(async { axum::serve(listener, app).await });
+
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let server = axum::serve(listener, app).with_graceful_shutdown(async {
+                let _ = stop_rx.await;
+            });
+            if let Err(e) = server.await {
+                log::error!("Webhook server failed: {e}");
+            }
+        });
+        *self.server.lock().expect("lock poisoned") = Some((stop_tx, task));
+    }
+
+    async fn shutdown(&self) {
+        let Some((stop_tx, task)) = self.server.lock().expect("lock poisoned").take() else {
+            return;
+        };
+        log::debug!("Stopping the webhook server on {}", self.listen);
+        let _ = stop_tx.send(());
+        let _ = task.await;
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl std::fmt::Debug for WebHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebHook")
+            .field("listen", &self.listen)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Notifies once, some time after being watched.
+    #[derive(Debug)]
+    struct Once(Arc<AtomicBool>);
+
+    #[async_trait]
+    impl Watch for Once {
+        async fn watch(&mut self, notify: Notifier) {
+            self.0.store(true, Ordering::SeqCst);
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                notify.notify_async(&"once").await;
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ready_and_heartbeat() {
+        let watched = Arc::new(AtomicBool::new(false));
+        let ready = Arc::new(AtomicBool::new(false));
+        let beats = Arc::new(AtomicUsize::new(0));
+        // the beats before and after handling the change.
+        let handled = Arc::new(std::sync::Mutex::new(None));
+
+        let mut sources = SourceRegistry::new(std::iter::empty());
+        let watcher = Box::new(Once(watched.clone())) as _;
+        let mut registry = WatcherRegistry::new(&mut sources, std::iter::once(watcher))
+            .with_ready({
+                let (watched, ready) = (watched.clone(), ready.clone());
+                move || ready.store(watched.load(Ordering::SeqCst), Ordering::SeqCst)
+            })
+            .with_heartbeat(Duration::from_millis(10), {
+                let beats = beats.clone();
+                move || {
+                    beats.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+
+        registry
+            .watch(
+                |_, _| {
+                    let (beats, handled) = (beats.clone(), handled.clone());
+                    async move {
+                        let before = beats.load(Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        *handled.lock().unwrap() = Some((before, beats.load(Ordering::SeqCst)));
+                    }
+                    .boxed()
+                },
+                tokio::time::sleep(Duration::from_millis(500)),
+            )
+            .await;
+
+        assert!(ready.load(Ordering::SeqCst), "ready before being watched");
+        let (before, after) = handled.lock().unwrap().unwrap();
+        assert!(before > 0);
+        assert_eq!(before, after, "beats while handling a change");
+        assert!(beats.load(Ordering::SeqCst) > after);
     }
 }